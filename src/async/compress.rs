@@ -17,12 +17,16 @@ use tokio_pipe::pipe;
 
 use crate::{
 	compress_type::{CompressThreads, CompressType},
+	config,
 	filter_spec::FilterSpec,
+	hardened,
 	path_utils::*,
+	process::{Resources, SpawnOptions, StderrCollector, StderrPolicy},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum Filter {
+	#[default]
 	NoFilter,
 	Filter(FilterSpec),
 }
@@ -46,17 +50,14 @@ fn piped_stdin<W: AsyncWriteExt + Unpin + Send + 'static>(buf: CheckBuf, mut wr:
 	});
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum PipeType {
 	Stdio(Stdio),
 	Pipe(CheckBuf),
+	#[default]
 	Stdin,
 }
 
-impl Default for PipeType {
-	fn default() -> Self { Self::Stdin }
-}
-
 impl Filter {
 	pub async fn new_read_filter<P: AsRef<Path>>(&self, name: Option<P>, buf: CheckBuf) -> io::Result<Box<dyn AsyncRead + Unpin>> {
 
@@ -109,14 +110,14 @@ impl Filter {
 		Ok(match ctype {
 			CompressType::NoFilter => Filter::NoFilter,
 			_ => {
-				let tool = ctype.get_decompress_tool()?;
+				let tool = ctype.decompress_tool()?;
 				
 				// Neither of the two statements below should panic unless something has gone wrong...
 				let path = tool.path().expect("Unknown path for selected tool");
 				let service = tool.get_decompress(ctype).expect("tool does not support selected decompress type");
 				
 				// Threads only have an effect on compression, so we leave them at their defaults here
-				Filter::Filter(FilterSpec::new_compress(path, service.args(CompressThreads::Default), ctype))
+				Filter::Filter(FilterSpec::new_compress(path, service.args(CompressThreads::Default), ctype).envs(service.envs(CompressThreads::Default)))
 			},
 		})
 	}
@@ -125,24 +126,38 @@ impl Filter {
 		Ok(match ctype {
 			CompressType::NoFilter => Filter::NoFilter,
 			_ => {
-				let tool = ctype.get_compress_tool()?;
+				let tool = ctype.compress_tool()?;
 
 				// Neither of the two statements below should panic unless something has gone wrong...
 				let path = tool.path().expect("Unknown path for selected tool");
 				let service = tool.get_compress(ctype).expect("tool does not support selected compress type");
+				let cthreads = tool.threads(service, cthreads);
 
-				Filter::Filter(FilterSpec::new_compress(path, service.args(cthreads), ctype))
+				Filter::Filter(FilterSpec::new_compress(path, service.args(cthreads), ctype).envs(service.envs(cthreads)))
 			},
 		})
 	}
 }
 
-impl Default for Filter {
-	fn default() -> Self { Self::NoFilter }
+// Applies the spawn options (see [`process`](crate::process)) and the environment variables of
+// the filter to a new command
+fn spawn_command(f: &FilterSpec) -> io::Result<Command> {
+	let mut com = std::process::Command::new(f.path());
+	f.get_spawn_options().apply(&mut com)?;
+	hardened::set_envs(&mut com, f.get_envs().iter().map(|(k, v)| (k, v)))?;
+	Ok(Command::from(com))
+}
+
+// Starts handling the stderr output of the child according to the stderr policy (which is not
+// StderrPolicy::Capture, as child failures are not reported)
+fn handle_stderr(proc: &mut Child, f: &FilterSpec) {
+	if let Some(fd) = proc.stderr.take().and_then(|e| e.into_owned_fd().ok()) {
+		let _ = StderrCollector::new(std::fs::File::from(fd), f.get_spawn_options().get_stderr());
+	}
 }
 
 pub async fn open_read_filter(f: &FilterSpec, input: PipeType) -> io::Result<ChildStdout> {
-	let mut com = Command::new(f.path());
+	let mut com = spawn_command(f)?;
 
 	let (com, buf) = match input {
 		PipeType::Stdio(s) => (com.stdin(s), None),
@@ -152,25 +167,29 @@ pub async fn open_read_filter(f: &FilterSpec, input: PipeType) -> io::Result<Chi
 
 	match com.args(f.args()).stdout(Stdio::piped()).spawn() {
 		Ok(mut proc) => {
+			handle_stderr(&mut proc, f);
 			if let Some(b) = buf {
 				let wr = proc.stdin.take().expect("pipe problems getting stdin");
 				piped_stdin(b, wr)
 			}
 			Ok(proc.stdout.expect("pipe problem"))
 		},
-		Err(error) => Err(Error::new(ErrorKind::Other, format!("Error executing pipe command '{}': {}", f.path().display(), error))),
+		Err(error) => Err(Error::other(format!("Error executing pipe command '{}': {}", f.path().display(), error))),
 	}
 }
 
 pub async fn open_write_filter(f: &FilterSpec, output: Option<Stdio>) -> io::Result<Child> {	
-	let mut com = Command::new(f.path());
+	let mut com = spawn_command(f)?;
 	let com = match output {
 		Some(s) => com.stdout(s),
 		None => com.stdout(Stdio::inherit()),
 	};
 	match com.args(f.args()).stdin(Stdio::piped()).spawn() {
-		Ok(proc) => Ok(proc),
-		Err(error) => Err(Error::new(ErrorKind::Other, format!("Error executing pipe command '{}': {}", f.path().display(), error))),
+		Ok(mut proc) => {
+			handle_stderr(&mut proc, f);
+			Ok(proc)
+		},
+		Err(error) => Err(Error::other(format!("Error executing pipe command '{}': {}", f.path().display(), error))),
 	}
 }
 
@@ -249,10 +268,16 @@ pub struct AsyncCompressIo {
 	ctype: CompressType,
 	cthreads: CompressThreads,
 	fix_path: bool,
+	stderr: StderrPolicy,
+	resources: Option<Resources>,
+	kill_on_parent_death: Option<bool>,
+	process_group: Option<bool>,
 }
 
 impl AsyncCompressIo {
-	pub fn new() -> Self { Self::default() }
+	pub fn new() -> Self { 
+		Self { cthreads: config::default_threads(), ..Default::default() }
+	}
 
 	pub fn path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self
 	{
@@ -281,14 +306,70 @@ impl AsyncCompressIo {
 		self
 	}
 
+	/// Sets how messages written to `stderr` by the compression utilities are handled (see
+	/// [`StderrPolicy`]).  As failures of the utilities are not reported by async readers and
+	/// writers, [`StderrPolicy::Capture`] is not supported: [`AsyncCompressIo::reader`] and
+	/// [`AsyncCompressIo::writer`] will return an error if it is set.
+	pub fn stderr(&mut self, policy: StderrPolicy) -> &mut Self {
+		self.stderr = policy;
+		self
+	}
+
+	/// Sets whether the compression utilities are killed if the thread creating the reader or
+	/// writer exits (see [`process`](crate::process)).  With a multi-threaded runtime this is
+	/// the worker thread running the task, so this should only be set if the runtime's threads
+	/// outlive the readers and writers.  The default is taken from
+	/// [`process::set_kill_on_parent_death`](crate::process::set_kill_on_parent_death).
+	pub fn kill_on_parent_death(&mut self, x: bool) -> &mut Self {
+		self.kill_on_parent_death = Some(x);
+		self
+	}
+
+	/// Sets whether the compression utilities are placed in their own process group (see
+	/// [`process`](crate::process)).  The default is taken from
+	/// [`process::set_process_group`](crate::process::set_process_group).
+	pub fn process_group(&mut self, x: bool) -> &mut Self {
+		self.process_group = Some(x);
+		self
+	}
+
+	/// Sets the resource controls for the compression utilities (see [`Resources`])
+	pub fn resources(&mut self, resources: Resources) -> &mut Self {
+		self.resources = Some(resources);
+		self
+	}
+
+	// Applies the stderr policy, resource controls and process settings to the filter
+	fn with_spawn_options(&self, filter: Filter) -> io::Result<Filter> {
+		if let StderrPolicy::Capture(_) = self.stderr {
+			return Err(Error::new(ErrorKind::InvalidInput, "Capturing stderr is not supported by async readers and writers"))
+		}
+		Ok(match filter {
+			Filter::Filter(f) => {
+				let mut opts: SpawnOptions = f.get_spawn_options().clone().stderr(self.stderr.clone());
+				if let Some(r) = self.resources.as_ref() {
+					opts = opts.resources(r.clone())
+				}
+				if let Some(x) = self.kill_on_parent_death {
+					opts = opts.kill_on_parent_death(x)
+				}
+				if let Some(x) = self.process_group {
+					opts = opts.process_group(x)
+				}
+				Filter::Filter(f.spawn_options(opts))
+			},
+			Filter::NoFilter => Filter::NoFilter,
+		})
+	}
+
 	pub async fn reader(&self) -> io::Result<Box<dyn AsyncRead + Unpin>> {
 		let mut buf = CheckBuf::default();
-		let filter = Filter::new_decompress_filter(check_read_ctype(self.path.as_ref(), self.ctype, Some(&mut buf))?)?;
+		let filter = self.with_spawn_options(Filter::new_decompress_filter(check_read_ctype(self.path.as_ref(), self.ctype, Some(&mut buf))?)?)?;
 		filter.new_read_filter(self.path.as_ref(), buf).await
 	}
 
 	pub async fn bufreader(&self) -> io::Result<BufReader<Box<dyn AsyncRead + Unpin>>> {
-		self.reader().await.map(|r| BufReader::new(r))
+		self.reader().await.map(BufReader::new)
 	}
 
 	pub async fn writer(&self) -> io::Result<Box<dyn AsyncWrite + Unpin>> {
@@ -301,11 +382,11 @@ impl AsyncCompressIo {
 		} else {
 			self.ctype
 		};
-		let filter = Filter::new_compress_filter(ctype, self.cthreads)?;
+		let filter = self.with_spawn_options(Filter::new_compress_filter(ctype, self.cthreads)?)?;
 		filter.new_write_filter(self.path.as_ref(), self.fix_path).await
 	}
 
 	pub async fn bufwriter(&self) -> io::Result<BufWriter<Box<dyn AsyncWrite + Unpin>>> {
-		self.writer().await.map(|w| BufWriter::new(w))
+		self.writer().await.map(BufWriter::new)
 	}
 }
@@ -0,0 +1,170 @@
+//! Command line access to `compress_io` format detection and tool selection.
+//!
+//! All subcommands exit with a non-zero status if any operation fails, including a
+//! compression or decompression utility exiting with a failure status.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+
+use compress_io::{
+    compress::{self, CompressIo},
+    compress_type::{CompressThreads, CompressType},
+    path_utils::check_read_ctype,
+    tools,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "compress-io",
+    version,
+    about = "Detect, decompress and recompress files"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the compression format of files (or stdin)
+    Detect { files: Vec<PathBuf> },
+    /// Decompress files (or stdin) to stdout
+    Cat { files: Vec<PathBuf> },
+    /// Recompress a file (or stdin) to a new file (or stdout)
+    Convert {
+        /// Input file [default: stdin]
+        #[arg(short, long)]
+        input: Option<PathBuf>,
+        /// Output file [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Output format [default: from output file suffix]
+        #[arg(short, long)]
+        format: Option<CompressType>,
+        /// Compression level
+        #[arg(short, long)]
+        level: Option<u32>,
+        /// Threads: default, ncores, nphyscores or a number
+        #[arg(short, long)]
+        threads: Option<CompressThreads>,
+        /// Do not add a compression suffix to the output file name
+        #[arg(long)]
+        fix_path: bool,
+    },
+    /// Check that files (or stdin) can be completely decompressed
+    Verify { files: Vec<PathBuf> },
+    /// Show the available tools and the tool selected for each format
+    Tools {
+        /// Test all available services
+        #[arg(long)]
+        self_test: bool,
+    },
+}
+
+// Apply f to each file, or to stdin if no files are given, reporting errors
+fn for_each_input<F>(files: &[PathBuf], mut f: F) -> bool
+where
+    F: FnMut(Option<&Path>) -> io::Result<()>,
+{
+    let mut ok = true;
+    let inputs: Vec<Option<&Path>> = if files.is_empty() {
+        vec![None]
+    } else {
+        files.iter().map(|p| Some(p.as_path())).collect()
+    };
+    for p in inputs {
+        if let Err(e) = f(p) {
+            let name = p.map(|p| p.display().to_string());
+            eprintln!(
+                "compress-io: {}: {}",
+                name.as_deref().unwrap_or("<stdin>"),
+                e
+            );
+            ok = false
+        }
+    }
+    ok
+}
+
+fn decompress_to<W: Write>(path: Option<&Path>, wrt: &mut W) -> io::Result<u64> {
+    let mut rdr = CompressIo::new().opt_path(path).reader()?;
+    let n = io::copy(&mut rdr, wrt)?;
+    rdr.finish()?;
+    Ok(n)
+}
+
+fn run(cli: Cli) -> bool {
+    match cli.command {
+        Command::Detect { files } => for_each_input(&files, |p| {
+            let ct = check_read_ctype(p, CompressType::Unknown, None)?;
+            println!(
+                "{}: {}",
+                p.map(|p| p.display().to_string())
+                    .as_deref()
+                    .unwrap_or("<stdin>"),
+                ct
+            );
+            Ok(())
+        }),
+        Command::Cat { files } => {
+            let mut out = io::stdout().lock();
+            for_each_input(&files, |p| decompress_to(p, &mut out).map(|_| ()))
+        }
+        Command::Verify { files } => for_each_input(&files, |p| {
+            let n = decompress_to(p, &mut io::sink())?;
+            if let Some(p) = p {
+                println!("{}: OK ({} bytes)", p.display(), n)
+            }
+            Ok(())
+        }),
+        Command::Convert {
+            input,
+            output,
+            format,
+            level,
+            threads,
+            fix_path,
+        } => for_each_input(input.as_slice(), |p| {
+            let mut src = CompressIo::new();
+            src.opt_path(p);
+            let mut cio = CompressIo::new();
+            cio.opt_path(output.as_ref());
+            if let Some(f) = format {
+                cio.ctype(f);
+            }
+            if let Some(l) = level {
+                cio.level(l);
+            }
+            if let Some(t) = threads {
+                cio.cthreads(t);
+            }
+            if fix_path {
+                cio.fix_path();
+            }
+            compress::transcode(&src, &cio)?.check()
+        }),
+        Command::Tools { self_test } => {
+            print!("{}", tools::registry_info());
+            if self_test {
+                let report = tools::self_test();
+                println!("{}", report);
+                report.passed()
+            } else {
+                true
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    if run(Cli::parse()) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,320 @@
+//! Timeouts and cancellation for readers and writers
+//!
+//! If a compression utility stops producing or consuming data (for example because it is
+//! reading from a hung network file system), reads from or writes to the child process can
+//! block indefinitely.  To guard against this, [`CompressIo`] readers and writers can be given
+//! an inactivity timeout ([`CompressIo::inactivity_timeout`]), which limits the time that any
+//! single read or write can wait for the child process, and a total timeout
+//! ([`CompressIo::timeout`]), which limits the lifetime of the reader or writer.  When a timeout
+//! expires the child process is killed and the pending operation returns an error of kind
+//! [`io::ErrorKind::TimedOut`].
+//!
+//! A [`CancelHandle`] registered with [`CompressIo::cancel_handle`] allows all readers and
+//! writers generated from the [`CompressIo`] to be cancelled from another thread.  Calling
+//! [`CancelHandle::cancel`] kills the child processes, and pending and subsequent operations
+//! return an error for which [`is_cancelled`] returns true.  Note that
+//! [`io::ErrorKind::Interrupted`] is not used for this as the standard library retries
+//! operations that fail with this error kind.
+//!
+//! Timeouts and cancellation apply to reads from and writes to child processes; reads from
+//! and writes to plain files and `stdin`/`stdout` are only checked for cancellation before
+//! the operation starts.  The wait for the child process to exit in [`Reader::finish`] and
+//! [`Writer::finish`] is also subject to the timeouts.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::{io::Read, thread, time::Duration};
+//! use compress_io::{cancel::{self, CancelHandle}, compress::CompressIo};
+//!
+//! fn main() -> std::io::Result<()> {
+//!   let handle = CancelHandle::new();
+//!   let mut rd = CompressIo::new()
+//!     .path("foo.gz")
+//!     .inactivity_timeout(Duration::from_secs(60))
+//!     .cancel_handle(&handle)
+//!     .reader()?;
+//!   let h = handle.clone();
+//!   thread::spawn(move || {
+//!     thread::sleep(Duration::from_secs(600));
+//!     h.cancel()
+//!   });
+//!   let mut contents = Vec::new();
+//!   match rd.read_to_end(&mut contents) {
+//!     Err(e) if cancel::is_cancelled(&e) => eprintln!("Cancelled"),
+//!     r => { r?; }
+//!   }
+//!   Ok(())
+//! }
+//! ```
+//!
+//! [`CompressIo`]: crate::compress::CompressIo
+//! [`CompressIo::inactivity_timeout`]: crate::compress::CompressIo::inactivity_timeout
+//! [`CompressIo::timeout`]: crate::compress::CompressIo::timeout
+//! [`CompressIo::cancel_handle`]: crate::compress::CompressIo::cancel_handle
+//! [`Reader::finish`]: crate::compress::Reader::finish
+//! [`Writer::finish`]: crate::compress::Writer::finish
+
+use std::{
+    error, fmt,
+    io::{self, Error, ErrorKind, Write},
+    os::unix::io::{AsRawFd, RawFd},
+    process::Child,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use os_pipe::{pipe, PipeReader, PipeWriter};
+
+// Interval between checks when waiting for a child process to exit
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The error payload used for operations that fail because they were cancelled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// Returns true if `e` is the result of a cancellation by [`CancelHandle::cancel`]
+pub fn is_cancelled(e: &io::Error) -> bool {
+    e.get_ref().map(|e| e.is::<Cancelled>()).unwrap_or(false)
+}
+
+fn cancelled_error() -> io::Error {
+    Error::other(Cancelled)
+}
+
+#[derive(Debug)]
+struct CancelInner {
+    cancelled: AtomicBool,
+    children: Mutex<Vec<u32>>,
+    // Written to on cancellation to wake up threads waiting in poll().  Nothing is read from
+    // the pipe, so once cancelled it remains readable.
+    wake_rd: PipeReader,
+    wake_wr: PipeWriter,
+}
+
+/// A handle that can be used to cancel readers and writers from another thread.  Clones of
+/// the handle refer to the same cancellation state.
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    inner: Arc<CancelInner>,
+}
+
+impl Default for CancelHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        let (wake_rd, wake_wr) = pipe().expect("Couldn't create pipe");
+        Self {
+            inner: Arc::new(CancelInner {
+                cancelled: AtomicBool::new(false),
+                children: Mutex::new(Vec::new()),
+                wake_rd,
+                wake_wr,
+            }),
+        }
+    }
+
+    /// Cancels all readers and writers associated with the handle, killing their child
+    /// processes.  Cancellation is permanent; readers and writers created later using the
+    /// same handle will fail immediately.
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for pid in self.inner.children.lock().unwrap().iter() {
+            unsafe {
+                libc::kill(*pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        let _ = (&self.inner.wake_wr).write(&[0]);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Children are registered so that they can be killed on cancellation.  A child must be
+    // deregistered *before* it is reaped to avoid killing an unrelated process that has
+    // been given the same pid.
+    fn register(&self, pid: u32) {
+        self.inner.children.lock().unwrap().push(pid)
+    }
+
+    fn deregister(&self, pid: u32) {
+        self.inner.children.lock().unwrap().retain(|p| *p != pid)
+    }
+
+    fn wake_fd(&self) -> RawFd {
+        self.inner.wake_rd.as_raw_fd()
+    }
+}
+
+/// Timeout and cancellation settings from [`CompressIo`](crate::compress::CompressIo)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Timeouts {
+    pub(crate) inactivity: Option<Duration>,
+    pub(crate) total: Option<Duration>,
+    pub(crate) cancel: Option<CancelHandle>,
+}
+
+impl Timeouts {
+    pub(crate) fn is_set(&self) -> bool {
+        self.inactivity.is_some() || self.total.is_some() || self.cancel.is_some()
+    }
+}
+
+/// Applies timeouts and cancellation to the operations of a single reader or writer
+#[derive(Debug)]
+pub(crate) struct Guard {
+    timeouts: Timeouts,
+    start: Instant,
+    last: Instant,
+    pid: Option<u32>,
+}
+
+impl Guard {
+    pub(crate) fn new(timeouts: &Timeouts, pid: Option<u32>) -> Self {
+        if let (Some(h), Some(pid)) = (timeouts.cancel.as_ref(), pid) {
+            h.register(pid)
+        }
+        let now = Instant::now();
+        Self {
+            timeouts: timeouts.clone(),
+            start: now,
+            last: now,
+            pid,
+        }
+    }
+
+    /// Deregisters the child process.  Must be called before the child is reaped.
+    pub(crate) fn release(&mut self) {
+        if let (Some(h), Some(pid)) = (self.timeouts.cancel.as_ref(), self.pid.take()) {
+            h.deregister(pid)
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let t1 = self.timeouts.inactivity.map(|d| self.last + d);
+        let t2 = self.timeouts.total.map(|d| self.start + d);
+        match (t1, t2) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Checks for cancellation before an operation on a file or stdin/stdout
+    pub(crate) fn check_cancelled(&self) -> io::Result<()> {
+        match self.timeouts.cancel.as_ref() {
+            Some(h) if h.is_cancelled() => Err(cancelled_error()),
+            _ => Ok(()),
+        }
+    }
+
+    // Returns an error if cancelled or if a timeout has expired
+    fn check(&self) -> io::Result<()> {
+        self.check_cancelled()?;
+        match self.deadline() {
+            Some(d) if d <= Instant::now() => Err(Error::new(
+                ErrorKind::TimedOut,
+                "Timed out waiting for compression utility",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records successful activity, resetting the inactivity timeout
+    pub(crate) fn activity(&mut self) {
+        self.last = Instant::now()
+    }
+
+    /// Waits until `fd` is ready for reading (or writing if `write` is true), returning an error
+    /// if the operation is cancelled or a timeout expires first.
+    pub(crate) fn wait_ready(&self, fd: RawFd, write: bool) -> io::Result<()> {
+        let events = if write { libc::POLLOUT } else { libc::POLLIN };
+        let mut fds = [
+            libc::pollfd {
+                fd,
+                events,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self
+                    .timeouts
+                    .cancel
+                    .as_ref()
+                    .map(|h| h.wake_fd())
+                    .unwrap_or(-1),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            self.check()?;
+            let timeout = match self.deadline() {
+                Some(d) => {
+                    let ms = d.saturating_duration_since(Instant::now()).as_micros() / 1000 + 1;
+                    ms.min(i32::MAX as u128) as libc::c_int
+                }
+                None => -1,
+            };
+            let n = fds.len() as libc::nfds_t;
+            if unsafe { libc::poll(fds.as_mut_ptr(), n, timeout) } < 0 {
+                let e = Error::last_os_error();
+                if e.kind() != ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            } else if fds[0].revents != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for `child` to exit *without* reaping it (so that the caller can collect the exit
+    /// status and resource usage).  Returns an error if the wait is cancelled or a timeout
+    /// expires, in which case the child should be killed.
+    pub(crate) fn wait_exit(&self, child: &Child) -> io::Result<()> {
+        loop {
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let r = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    child.id() as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+                )
+            };
+            if r < 0 {
+                let e = Error::last_os_error();
+                if e.kind() != ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            } else if unsafe { info.si_pid() } != 0 {
+                return Ok(());
+            }
+            self.check()?;
+            thread::sleep(WAIT_POLL_INTERVAL)
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.release()
+    }
+}
@@ -1,19 +1,33 @@
 use std::{
     fs::File,
-    io::{self, stdin, stdout, BufReader, BufWriter, Error, ErrorKind, Read, Stdin, Stdout, Write},
+    io::{self, stdin, stdout, BufReader, BufWriter, Error, Read, Stdin, Stdout, Write},
+    os::unix::io::AsRawFd,
     path::{Path, PathBuf},
-    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
-    thread,
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
-use crate::compress_type::{CompressThreads, CompressType};
+use crate::cancel::{CancelHandle, Guard, Timeouts};
+use crate::compress_type::{get_ctype, CompressThreads, CompressType};
+use crate::config;
 use crate::filter_spec::FilterSpec;
+use crate::hardened;
 use crate::path_utils::*;
+use crate::process::{self, Completion, Resources, SpawnOptions, StderrCollector, StderrPolicy};
+use crate::progress::{Progress, ProgressFn, ProgressHook, ProgressState};
+use crate::stats::{wait_child, CpuTime, IoStats, Tracker};
+use crate::tools::{self, ToolRegister};
 
-use os_pipe::{pipe, PipeReader};
+use os_pipe::{pipe, PipeReader, PipeWriter};
 
-#[derive(Debug)]
+#[cfg(target_os = "linux")]
+mod fast_copy;
+
+#[derive(Debug, Default)]
 pub enum Filter {
+    #[default]
     NoFilter,
     Filter(FilterSpec),
 }
@@ -36,6 +50,44 @@ fn piped_stdin(buf: CheckBuf) -> PipeReader {
     reader
 }
 
+// Thread copying `head` followed by the remainder of `src` into `dst` (the input of the
+// decompression utility, or a pipe if no utility is used).  A broken pipe is not treated as an
+// error as it only occurs if the reader has been dropped or the utility has exited early, which
+// is reported separately.
+fn spawn_pump<R, W>(head: Vec<u8>, mut src: R, mut dst: W) -> io::Result<JoinHandle<io::Result<()>>>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::Builder::new()
+        .name("compress-io-pump".to_owned())
+        .spawn(move || {
+            match dst
+                .write_all(&head)
+                .and_then(|_| io::copy(&mut src, &mut dst))
+            {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                res => res.map(|_| ()),
+            }
+        })
+}
+
+// Thread copying the output of the compression utility (or of a pipe if no utility is used)
+// into `sink`, returning the sink once the output has been closed
+fn spawn_sink_pump<R, W>(mut src: R, mut sink: W) -> io::Result<JoinHandle<io::Result<W>>>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::Builder::new()
+        .name("compress-io-pump".to_owned())
+        .spawn(move || {
+            io::copy(&mut src, &mut sink)?;
+            sink.flush()?;
+            Ok(sink)
+        })
+}
+
 impl Filter {
     pub fn reader<P: AsRef<Path>>(&self, name: Option<P>, buf: CheckBuf) -> io::Result<Reader> {
         let pipe = if name.is_none() && !buf.is_empty() {
@@ -55,33 +107,57 @@ impl Filter {
             }
             Filter::Filter(f) => {
                 if let Some(s) = name {
-                    Reader::from_child(open_read_filter(f, Some(File::open(s.as_ref())?))?)
+                    debug!(
+                        "Reading {} through {}",
+                        s.as_ref().display(),
+                        f.path().display()
+                    );
+                    let file = File::open(s.as_ref())?;
+                    let tracked = file.try_clone()?;
+                    let (child, stderr) = read_filter(f, Some(file))?;
+                    Reader::from_child(child)
+                        .with_stderr(stderr)
+                        .track_file(tracked)
                 } else {
-                    Reader::from_child(open_read_filter(f, pipe)?)
+                    let (child, stderr) = read_filter(f, pipe)?;
+                    Reader::from_child(child).with_stderr(stderr)
                 }
             }
         })
     }
 
+    /// Generates a reader from `src`, which is copied to the decompression utility (if any) by
+    /// a separate thread.  `head` holds any bytes already read from `src` (i.e., to determine
+    /// the compression type), and is passed on before the remainder of `src`.  Errors reading
+    /// from `src` are returned by the reader once the data before the error has been read.
+    pub fn reader_from<R: Read + Send + 'static>(
+        &self,
+        head: Vec<u8>,
+        src: R,
+    ) -> io::Result<Reader> {
+        Ok(match self {
+            Filter::NoFilter => {
+                let (rd, wr) = pipe()?;
+                Reader::from_pipe_reader(rd).with_pump(spawn_pump(head, src, wr)?)
+            }
+            Filter::Filter(f) => {
+                debug!("Reading from source through {}", f.path().display());
+                let (mut child, stderr) = spawn_tracked(f, Stdio::piped(), Stdio::piped())?;
+                let input = child.stdin.take().expect("Error getting child stdin");
+                // If the thread can not be started the reader is dropped, killing the child
+                let rdr = Reader::from_child(child).with_stderr(stderr);
+                rdr.with_pump(spawn_pump(head, src, input)?)
+            }
+        })
+    }
+
     pub fn writer<P: AsRef<Path>>(
         &self,
         name: Option<P>,
         fix_path: bool,
         no_wait: bool,
     ) -> io::Result<Writer> {
-        // Add compression suffix if required (and not already present and fix_path is not set)
-        let name = match (name, self) {
-            (Some(p), Filter::Filter(f)) => {
-                if fix_path {
-                    Some(p.as_ref().to_owned())
-                } else {
-                    Some(f.cond_add_suffix(p.as_ref()))
-                }
-            }
-            (Some(p), _) => Some(p.as_ref().to_owned()),
-            _ => None,
-        };
-
+        let name = self.output_path(name, fix_path);
         Ok(match self {
             Filter::NoFilter => {
                 if let Some(s) = name {
@@ -92,20 +168,95 @@ impl Filter {
             }
             Filter::Filter(f) => {
                 if let Some(s) = name {
-                    Writer::from_child(open_write_filter(f, Some(File::create(&s)?))?, no_wait)
+                    debug!("Writing {} through {}", s.display(), f.path().display());
+                    let file = File::create(&s)?;
+                    let tracked = file.try_clone()?;
+                    let (child, stderr) = write_filter(f, Some(file), no_wait)?;
+                    Writer::from_child(child, no_wait)
+                        .with_stderr(stderr)
+                        .track_file(tracked)
                 } else {
                     let none: Option<File> = None;
-                    Writer::from_child(open_write_filter(f, none)?, no_wait)
+                    let (child, stderr) = write_filter(f, none, no_wait)?;
+                    Writer::from_child(child, no_wait).with_stderr(stderr)
                 }
             }
         })
     }
 
+    /// Generates a writer whose output is copied into `sink` by a separate thread (see
+    /// [`SinkWriter`]).  The child process (if any) is always waited on when the writer is
+    /// finished.
+    pub fn writer_into<W: Write + Send + 'static>(&self, sink: W) -> io::Result<SinkWriter<W>> {
+        Ok(match self {
+            Filter::NoFilter => {
+                let (rd, wr) = pipe()?;
+                let pump = spawn_sink_pump(rd, sink)?;
+                SinkWriter::new(Writer::from_pipe_writer(wr), pump)
+            }
+            Filter::Filter(f) => {
+                debug!("Writing to sink through {}", f.path().display());
+                let (mut child, stderr) = spawn_tracked(f, Stdio::piped(), Stdio::piped())?;
+                let output = child.stdout.take().expect("Error getting child stdout");
+                // If the thread can not be started the writer is dropped, closing the input of
+                // the child and waiting for it to exit
+                let wrt = Writer::from_child(child, false).with_stderr(stderr);
+                let pump = spawn_sink_pump(output, sink)?;
+                SinkWriter::new(wrt, pump)
+            }
+        })
+    }
+
+    /// Sets the options used when spawning the utility (see [`process`](crate::process)).  Has
+    /// no effect if no utility is used.
+    pub fn with_spawn_options(self, options: SpawnOptions) -> Self {
+        match self {
+            Filter::Filter(f) => Filter::Filter(f.spawn_options(options)),
+            Filter::NoFilter => Filter::NoFilter,
+        }
+    }
+
+    // Name of the compression utility used by the filter
+    #[cfg(feature = "progress-bar")]
+    pub(crate) fn tool_name(&self) -> Option<&str> {
+        match self {
+            Filter::Filter(f) => f.path().file_name().and_then(|s| s.to_str()),
+            Filter::NoFilter => None,
+        }
+    }
+
+    // Add compression suffix if required (and not already present and fix_path is not set)
+    pub(crate) fn output_path<P: AsRef<Path>>(
+        &self,
+        name: Option<P>,
+        fix_path: bool,
+    ) -> Option<PathBuf> {
+        match (name, self) {
+            (Some(p), Filter::Filter(f)) => {
+                if fix_path {
+                    Some(p.as_ref().to_owned())
+                } else {
+                    Some(f.cond_add_suffix(p.as_ref()))
+                }
+            }
+            (Some(p), _) => Some(p.as_ref().to_owned()),
+            _ => None,
+        }
+    }
+
     pub fn new_decompress_filter(ctype: CompressType) -> io::Result<Self> {
+        Self::decompress_filter_in(&tools::registry(), ctype)
+    }
+
+    // As new_decompress_filter() but selecting the utility from `reg`
+    pub(crate) fn decompress_filter_in(
+        reg: &ToolRegister,
+        ctype: CompressType,
+    ) -> io::Result<Self> {
         Ok(match ctype {
             CompressType::NoFilter => Filter::NoFilter,
             _ => {
-                let tool = ctype.get_decompress_tool()?;
+                let tool = ctype.decompress_tool_in(reg)?;
 
                 // Neither of the two statements below should panic unless something has gone wrong...
                 let path = tool.path().expect("Unknown path for selected tool");
@@ -114,88 +265,182 @@ impl Filter {
                     .expect("tool does not support selected decompress type");
 
                 // Threads only have an effect on compression, so we leave them at their defaults here
-                Filter::Filter(FilterSpec::new_compress(
-                    path,
-                    service.args(CompressThreads::Default),
-                    ctype,
-                ))
+                Filter::Filter(
+                    FilterSpec::new_compress(path, service.args(CompressThreads::Default), ctype)
+                        .envs(service.envs(CompressThreads::Default)),
+                )
             }
         })
     }
 
     pub fn new_compress_filter(ctype: CompressType, cthreads: CompressThreads) -> io::Result<Self> {
+        Self::new_compress_filter_with_level(ctype, cthreads, None)
+    }
+
+    /// As [`Filter::new_compress_filter`] but with an optional compression level.  The level is
+    /// ignored if the selected utility does not have a level option.
+    pub fn new_compress_filter_with_level(
+        ctype: CompressType,
+        cthreads: CompressThreads,
+        level: Option<u32>,
+    ) -> io::Result<Self> {
+        Self::compress_filter_in(&tools::registry(), ctype, cthreads, level)
+    }
+
+    // As new_compress_filter_with_level() but selecting the utility from `reg`
+    pub(crate) fn compress_filter_in(
+        reg: &ToolRegister,
+        ctype: CompressType,
+        cthreads: CompressThreads,
+        level: Option<u32>,
+    ) -> io::Result<Self> {
         Ok(match ctype {
             CompressType::NoFilter => Filter::NoFilter,
             _ => {
-                let tool = ctype.get_compress_tool()?;
+                let tool = ctype.compress_tool_in(reg)?;
                 // Neither of the two statements below should panic unless something has gone wrong...
                 let path = tool.path().expect("Unknown path for selected tool");
                 let service = tool
                     .get_compress(ctype)
                     .expect("tool does not support selected compress type");
-                Filter::Filter(FilterSpec::new_compress(
-                    path,
-                    service.args(cthreads),
-                    ctype,
-                ))
+                let cthreads = tool.threads(service, cthreads);
+                Filter::Filter(
+                    FilterSpec::new_compress(path, service.args_with_level(cthreads, level), ctype)
+                        .envs(service.envs_with_level(cthreads, level)),
+                )
             }
         })
     }
 }
 
-impl Default for Filter {
-    fn default() -> Self {
-        Self::NoFilter
-    }
-}
+// The children spawned by open_read_filter() and open_write_filter() are not registered (see
+// process::shutdown_all_children()) as they are reaped by the caller, so could not be
+// deregistered
 
 pub fn open_read_filter<T: Into<Stdio>>(f: &FilterSpec, input: Option<T>) -> io::Result<Child> {
-    let mut com = Command::new(f.path());
-    let com = match input {
-        Some(s) => com.stdin(s),
-        None => com.stdin(Stdio::inherit()),
-    };
-    match com.args(f.args()).stdout(Stdio::piped()).spawn() {
-        //Ok(proc) => Ok(proc.stdout.expect("pipe problem")),
-        Ok(proc) => Ok(proc),
-        Err(error) => Err(Error::new(
-            ErrorKind::Other,
-            format!(
-                "Error executing pipe command '{}': {}",
-                f.path().display(),
-                error
-            ),
-        )),
-    }
+    let input = input.map(|s| s.into()).unwrap_or_else(Stdio::inherit);
+    spawn_filter(f, input, Stdio::piped()).map(|(c, _)| c)
 }
 
 pub fn open_write_filter<T: Into<Stdio> + std::fmt::Debug>(
     f: &FilterSpec,
     output: Option<T>,
 ) -> io::Result<Child> {
-    let mut com = Command::new(f.path());
-    let com = match output {
-        Some(s) => com.stdout(s),
-        None => com.stdout(Stdio::inherit()),
-    };
-    match com.args(f.args()).stdin(Stdio::piped()).spawn() {
-        Ok(proc) => Ok(proc),
-        Err(error) => Err(Error::new(
-            ErrorKind::Other,
-            format!(
-                "Error executing pipe command '{}': {}",
+    let output = output.map(|s| s.into()).unwrap_or_else(Stdio::inherit);
+    spawn_filter(f, Stdio::piped(), output).map(|(c, _)| c)
+}
+
+fn read_filter<T: Into<Stdio>>(
+    f: &FilterSpec,
+    input: Option<T>,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    let input = input.map(|s| s.into()).unwrap_or_else(Stdio::inherit);
+    spawn_tracked(f, input, Stdio::piped())
+}
+
+// If `no_wait` is set the child is reaped in the background, so must not be killed when the
+// thread creating the writer exits
+fn write_filter<T: Into<Stdio>>(
+    f: &FilterSpec,
+    output: Option<T>,
+    no_wait: bool,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    let output = output.map(|s| s.into()).unwrap_or_else(Stdio::inherit);
+    let opts = f.get_spawn_options();
+    if no_wait && opts.get_kill_on_parent_death() {
+        let opts = opts.clone().kill_on_parent_death(false);
+        spawn_tracked_with(f, &opts, Stdio::piped(), output)
+    } else {
+        spawn_tracked(f, Stdio::piped(), output)
+    }
+}
+
+// As spawn_filter(), also registering the child so that it can be terminated by
+// process::shutdown_all_children().  The child must be deregistered before it is reaped.
+fn spawn_tracked(
+    f: &FilterSpec,
+    input: Stdio,
+    output: Stdio,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    spawn_tracked_with(f, f.get_spawn_options(), input, output)
+}
+
+fn spawn_tracked_with(
+    f: &FilterSpec,
+    opts: &SpawnOptions,
+    input: Stdio,
+    output: Stdio,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    let (child, stderr) = spawn_filter_with(f, opts, input, output)?;
+    process::register(&child, opts);
+    Ok((child, stderr))
+}
+
+// Spawns the utility, also returning the handler for its stderr output if required by the
+// stderr policy
+fn spawn_filter(
+    f: &FilterSpec,
+    input: Stdio,
+    output: Stdio,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    spawn_filter_with(f, f.get_spawn_options(), input, output)
+}
+
+// As spawn_filter() but with spawn options other than those of `f`
+fn spawn_filter_with(
+    f: &FilterSpec,
+    opts: &SpawnOptions,
+    input: Stdio,
+    output: Stdio,
+) -> io::Result<(Child, Option<StderrCollector>)> {
+    let mut cmd = Command::new(f.path());
+    opts.apply(&mut cmd)?;
+    hardened::set_envs(&mut cmd, f.get_envs().iter().map(|(k, v)| (k, v)))?;
+    match cmd.args(f.args()).stdin(input).stdout(output).spawn() {
+        Ok(mut proc) => {
+            debug!(
+                "Spawned {} {} (pid {})",
                 f.path().display(),
-                error
-            ),
-        )),
+                f.args()
+                    .iter()
+                    .map(|a| a.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                proc.id()
+            );
+            let stderr = proc
+                .stderr
+                .take()
+                .and_then(|e| StderrCollector::new(e, opts.get_stderr()));
+            Ok((proc, stderr))
+        }
+        Err(error) => Err(Error::other(format!(
+            "Error executing pipe command '{}': {}",
+            f.path().display(),
+            error
+        ))),
     }
 }
 
 /// A compressed writer generated (normally) by [`CompressIo::writer`] or
 /// [`CompressIo::bufwriter`].
 ///
+/// The writer keeps track of the number of bytes written and other statistics,
+/// available from [`Writer::stats`].
+///
+/// The destination of the writer is given by [`Writer::get_ref`] or [`Writer::get_mut`].
 #[derive(Debug)]
-pub enum Writer {
+pub struct Writer {
+    inner: WriterKind,
+    stats: Tracker,
+    guard: Option<Guard>,
+    completion: Option<Completion>,
+    stderr: Option<StderrCollector>,
+}
+
+/// The destination of a [`Writer`]
+#[derive(Debug)]
+pub enum WriterKind {
     /// Writer created from a [`std::fs::File`]
     File(File),
 
@@ -204,69 +449,267 @@ pub enum Writer {
     Child(Option<ChildStdin>, Option<Child>),
 
     /// Writer created from a [`std::process::ChildStdin`] which will be not waited on
-    /// when the instance is dropped
+    /// when the instance is dropped (the child process is waited on in the background)
     ChildStdin(ChildStdin),
 
     /// Writer created from a [`std::io::Stdout`]
     Stdout(Stdout),
+
+    /// Writer created from a [`os_pipe::PipeWriter`]
+    PipeWriter(PipeWriter),
 }
 
 impl Writer {
-    /// Create a writer from a [`std::process::Child`].  If `no_wait` is false create
-    /// a [`Writer::Child`] instance that will wait for the child process
-    /// to end when the writer is dropped, otherwise it will create a
-    /// [`Writer::ChildStdin`] instance that will not wait.  In this case
-    /// care must be taken if the output file is to be opened for reading
-    /// immediately after the writer is dropped as there is no assurance that
-    /// the data has been completely written to disk
+    fn new(inner: WriterKind) -> Self {
+        Self {
+            inner,
+            stats: Tracker::new(),
+            guard: None,
+            completion: None,
+            stderr: None,
+        }
+    }
+
+    /// Create a writer from a [`std::process::Child`].  If `no_wait` is false the writer
+    /// will wait for the child process to end when the writer is dropped, otherwise
+    /// it will not wait.  In this case care must be taken if the output file is to be
+    /// opened for reading immediately after the writer is dropped as there is no assurance
+    /// that the data has been completely written to disk.  The child process is instead
+    /// waited on by a background thread, and [`Writer::completion`] can be used to find out
+    /// when it has finished.
     pub fn from_child(mut child: Child, no_wait: bool) -> Self {
         let cs = child.stdin.take().expect("Pipe error");
         if no_wait {
-            Self::ChildStdin(cs)
+            let mut wrt = Self::new(WriterKind::ChildStdin(cs));
+            wrt.completion = Some(process::reap_in_background(child));
+            wrt
         } else {
-            Self::Child(Some(cs), Some(child))
+            Self::new(WriterKind::Child(Some(cs), Some(child)))
         }
     }
 
-    /// Returns the [`std::process::Child`] instance from a [`Writer::Child`].  Has no effect
-    /// on other variants.  If called then the child process will *not* be waited
+    /// Returns the [`std::process::Child`] instance from a writer created by
+    /// [`Writer::from_child`] without `no_wait`.  Has no effect on other writers.
+    /// If called then the child process will *not* be waited
     /// on when the writer is dropped, and the caller can wait for the child to
     /// finish when required.
     ///
     /// Important! If `wait` is called before the writer is dropped then
     /// the wait call can block.
     pub fn take_child(&mut self) -> Option<Child> {
-        match self {
-            Self::Child(_, ch) => ch.take(),
+        match &mut self.inner {
+            WriterKind::Child(_, ch) => {
+                if let Some(g) = self.guard.as_mut() {
+                    g.release()
+                }
+                if let Some(c) = ch.as_ref() {
+                    process::deregister(c);
+                }
+                ch.take()
+            }
             _ => None,
         }
     }
 
     pub fn from_file(file: File) -> Self {
-        Self::File(file)
+        let tracked = file.try_clone().ok();
+        let wrt = Self::new(WriterKind::File(file));
+        match tracked {
+            Some(f) => wrt.track_file(f),
+            None => wrt,
+        }
     }
 
     pub fn from_stdout() -> Self {
-        Self::Stdout(stdout())
+        Self::new(WriterKind::Stdout(stdout()))
+    }
+
+    pub fn from_pipe_writer(pw: PipeWriter) -> Self {
+        Self::new(WriterKind::PipeWriter(pw))
+    }
+
+    /// Returns the destination of the writer
+    pub fn get_ref(&self) -> &WriterKind {
+        &self.inner
+    }
+
+    /// Returns the destination of the writer.  Writing directly to the destination bypasses
+    /// the statistics, timeouts and cancellation of the writer.  To wait on the child process
+    /// use [`Writer::take_child`].
+    pub fn get_mut(&mut self) -> &mut WriterKind {
+        &mut self.inner
+    }
+
+    // Output file, used to count the compressed bytes written
+    pub(crate) fn track_file(mut self, file: File) -> Self {
+        self.stats.set_file(file);
+        self
+    }
+
+    pub(crate) fn with_stderr(mut self, stderr: Option<StderrCollector>) -> Self {
+        self.stderr = stderr;
+        self
+    }
+
+    pub(crate) fn set_progress(&mut self, progress: ProgressState) {
+        self.stats.set_progress(progress)
+    }
+
+    pub(crate) fn set_timeouts(&mut self, timeouts: &Timeouts) {
+        let pid = match &self.inner {
+            WriterKind::Child(_, Some(c)) => Some(c.id()),
+            _ => None,
+        };
+        self.guard = Some(Guard::new(timeouts, pid))
+    }
+
+    /// Returns the current statistics for the writer (see [`stats`](crate::stats))
+    pub fn stats(&self) -> IoStats {
+        self.stats.stats()
+    }
+
+    /// For a writer created with `no_wait`, returns a handle that can be used to wait for the
+    /// child process to finish (see [`process`](crate::process)).  Returns `None` for other
+    /// writers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut wrt = CompressIo::new().path("foo.gz").no_wait().writer()?;
+    ///   let completion = wrt.completion().expect("No child process");
+    ///   writeln!(wrt, "Hello world")?;
+    ///   wrt.finish()?;
+    ///   // Do other things...
+    ///   completion.check()
+    /// }
+    /// ```
+    pub fn completion(&self) -> Option<Completion> {
+        self.completion.clone()
+    }
+
+    /// Flushes the writer and, for a writer created from a child process, closes the input to
+    /// the child process and waits for it to finish.  Unlike dropping the writer, this reports
+    /// errors, including the child process exiting with a failure status.  If the writer was
+    /// created with `no_wait` then the child process is not waited on, so only errors from
+    /// flushing are reported (use [`Writer::completion`] to check the child process).  On success the final statistics for the writer are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut wrt = CompressIo::new().path("foo.gz").bufwriter()?;
+    ///   writeln!(wrt, "Hello world")?;
+    ///   let stats = wrt.into_inner()?.finish()?;
+    ///   eprintln!("Wrote {} bytes", stats.bytes);
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn finish(mut self) -> io::Result<IoStats> {
+        self.flush()?;
+        let mut cpu = None;
+        if let WriterKind::Child(cs, ch) = &mut self.inner {
+            drop(cs.take());
+            if let Some(child) = ch.take() {
+                let (status, usage) = reap_child(self.guard.as_mut(), child)?;
+                let stderr = self.stderr.take().and_then(|s| s.finish());
+                check_status(status).map_err(|e| process::add_stderr(e, stderr))?;
+                cpu = Some(usage)
+            }
+        }
+        self.stats.finish(cpu);
+        Ok(self.stats())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Writer {
+    // File descriptor suitable for writing to directly, bypassing any buffering
+    fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        match &self.inner {
+            WriterKind::File(f) => Some(f.as_raw_fd()),
+            WriterKind::Child(Some(c), _) => Some(c.as_raw_fd()),
+            WriterKind::ChildStdin(c) => Some(c.as_raw_fd()),
+            WriterKind::PipeWriter(pw) => Some(pw.as_raw_fd()),
+            _ => None,
+        }
+    }
+}
+
+// Waits for a child process to exit (subject to any timeouts or cancellation) and reaps it
+fn reap_child(guard: Option<&mut Guard>, mut child: Child) -> io::Result<(ExitStatus, CpuTime)> {
+    if let Some(g) = guard {
+        let res = g.wait_exit(&child);
+        if res.is_err() {
+            let _ = child.kill();
+        }
+        g.release();
+        if let Err(e) = res {
+            let _ = wait_child(child);
+            return Err(e);
+        }
+    }
+    wait_child(child)
+}
+
+pub(crate) fn check_status(status: ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("Child process failed: {}", status)))
     }
 }
 
 impl Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            Self::File(f) => f.write(buf),
-            Self::Child(Some(c), _) => c.write(buf),
-            Self::ChildStdin(c) => c.write(buf),
-            Self::Stdout(s) => s.write(buf),
+        let mut buf = buf;
+        if let Some(g) = self.guard.as_ref() {
+            let res = match &self.inner {
+                WriterKind::Child(Some(c), _) | WriterKind::ChildStdin(c) => {
+                    // Once the pipe is ready, a write of up to PIPE_BUF bytes will not block
+                    buf = &buf[..buf.len().min(libc::PIPE_BUF)];
+                    g.wait_ready(c.as_raw_fd(), true)
+                }
+                WriterKind::PipeWriter(pw) => {
+                    buf = &buf[..buf.len().min(libc::PIPE_BUF)];
+                    g.wait_ready(pw.as_raw_fd(), true)
+                }
+                _ => g.check_cancelled(),
+            };
+            if let Err(e) = res {
+                if let WriterKind::Child(_, Some(c)) = &mut self.inner {
+                    let _ = c.kill();
+                }
+                return Err(e);
+            }
+        }
+        let n = match &mut self.inner {
+            WriterKind::File(f) => f.write(buf),
+            WriterKind::Child(Some(c), _) => c.write(buf),
+            WriterKind::ChildStdin(c) => c.write(buf),
+            WriterKind::Stdout(s) => s.write(buf),
+            WriterKind::PipeWriter(pw) => pw.write(buf),
             _ => Ok(0),
+        }?;
+        if let Some(g) = self.guard.as_mut() {
+            g.activity()
         }
+        self.stats.add(n);
+        Ok(n)
     }
     fn flush(&mut self) -> io::Result<()> {
-        match self {
-            Self::File(f) => f.flush(),
-            Self::Child(Some(c), _) => c.flush(),
-            Self::ChildStdin(c) => c.flush(),
-            Self::Stdout(s) => s.flush(),
+        match &mut self.inner {
+            WriterKind::File(f) => f.flush(),
+            WriterKind::Child(Some(c), _) => c.flush(),
+            WriterKind::ChildStdin(c) => c.flush(),
+            WriterKind::Stdout(s) => s.flush(),
+            WriterKind::PipeWriter(pw) => pw.flush(),
             _ => Ok(()),
         }
     }
@@ -274,27 +717,132 @@ impl Write for Writer {
 
 impl Drop for Writer {
     fn drop(&mut self) {
-        if let Self::Child(cs, ch) = self {
-            if let Some(mut child) = ch.take() {
+        if let WriterKind::Child(cs, ch) = &mut self.inner {
+            if let Some(child) = ch.take() {
                 drop(cs.take());
-                let _ = child.wait();
+                let _ = reap_child(self.guard.as_mut(), child);
             }
         }
     }
 }
 
+/// A compressed writer generated by [`CompressIo::writer_into`], sending its output to a
+/// user supplied sink.  The output is copied into the sink by a separate thread, and the sink
+/// is returned by [`SinkWriter::finish`].  If the writer is dropped without calling
+/// [`SinkWriter::finish`] then the sink is dropped once the remaining output has been copied.
+#[derive(Debug)]
+pub struct SinkWriter<W> {
+    writer: Writer,
+    pump: Option<JoinHandle<io::Result<W>>>,
+    // Result from the thread if it has already been waited on
+    result: Option<io::Result<W>>,
+}
+
+impl<W> SinkWriter<W> {
+    fn new(writer: Writer, pump: JoinHandle<io::Result<W>>) -> Self {
+        Self {
+            writer,
+            pump: Some(pump),
+            result: None,
+        }
+    }
+
+    /// Returns the current statistics for the writer (see [`stats`](crate::stats))
+    pub fn stats(&self) -> IoStats {
+        self.writer.stats()
+    }
+
+    /// Finishes the writer (see [`Writer::finish`]), waits for all of the output to be copied
+    /// into the sink, and returns the sink.  An error writing to the sink is reported in
+    /// preference to any resulting failure of the compression utility.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut wrt = CompressIo::new().writer_into(Vec::new())?;
+    ///   writeln!(wrt, "Hello world")?;
+    ///   let buf = wrt.finish()?;
+    ///   assert_eq!(buf, b"Hello world\n");
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn finish(self) -> io::Result<W> {
+        let Self {
+            writer,
+            pump,
+            result,
+        } = self;
+        // This closes the input to the child process (or the pipe), so the thread will finish
+        let res = writer.finish();
+        let sink = match result {
+            Some(r) => r,
+            None => join_sink_pump(pump.expect("Missing output thread")),
+        }?;
+        res.map(|_| sink)
+    }
+
+    // Error from the thread copying to the sink if it has failed.  The thread is only waited
+    // on if it has finished or the output has been closed (so it will finish shortly).
+    fn sink_error(&mut self, e: &Error) -> Option<Error> {
+        let finished = self.pump.as_ref().map(|h| h.is_finished()).unwrap_or(false);
+        if finished || (self.pump.is_some() && e.kind() == io::ErrorKind::BrokenPipe) {
+            self.result = self.pump.take().map(join_sink_pump);
+        }
+        match self.result.as_ref() {
+            Some(Err(e)) => Some(Error::new(e.kind(), e.to_string())),
+            _ => None,
+        }
+    }
+}
+
+fn join_sink_pump<W>(h: JoinHandle<io::Result<W>>) -> io::Result<W> {
+    h.join()
+        .unwrap_or_else(|_| Err(Error::other("Output thread panicked")))
+}
+
+impl<W> Write for SinkWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer
+            .write(buf)
+            .map_err(|e| self.sink_error(&e).unwrap_or(e))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer
+            .flush()
+            .map_err(|e| self.sink_error(&e).unwrap_or(e))
+    }
+}
+
 /// A compressed reader generated (normally) by [`CompressIo::reader`] or
 /// [`CompressIo::bufreader`].
 ///
+/// The reader keeps track of the number of bytes read and other statistics,
+/// available from [`Reader::stats`].
+///
+/// The source of the reader is given by [`Reader::get_ref`] or [`Reader::get_mut`].
+#[derive(Debug)]
+pub struct Reader {
+    inner: ReaderKind,
+    stats: Tracker,
+    guard: Option<Guard>,
+    stderr: Option<StderrCollector>,
+    // Thread copying the input from a source given to CompressIo::reader_from()
+    pump: Option<JoinHandle<io::Result<()>>>,
+}
 
+/// The source of a [`Reader`]
 #[derive(Debug)]
-pub enum Reader {
+pub enum ReaderKind {
     /// Reader created from a [`std::fs::File`]
     File(File),
 
-    /// Reader created from a [`std::process::ChildStdout`]
-    // ChildStdout(ChildStdout),
-    Child(Child, ChildStdout),
+    /// Reader created from a [`std::process::ChildStdout`].  The child is `None` once
+    /// it has been waited on
+    Child(Option<Child>, ChildStdout),
 
     /// Reader created from [`std::io::Stdin`]
     Stdin(Stdin),
@@ -305,40 +853,233 @@ pub enum Reader {
 
 impl Drop for Reader {
     fn drop(&mut self) {
-        if let Self::Child(c, _) = self {
+        if let ReaderKind::Child(Some(c), _) = &mut self.inner {
             let _ = c.kill();
-            let _ = c.wait();
+            if let Some(g) = self.guard.as_mut() {
+                g.release()
+            }
+            let _ = process::wait(c);
         }
     }
 }
 
 impl Reader {
+    fn new(inner: ReaderKind) -> Self {
+        Self {
+            inner,
+            stats: Tracker::new(),
+            guard: None,
+            stderr: None,
+            pump: None,
+        }
+    }
+
     pub fn from_file(file: File) -> Self {
-        Self::File(file)
+        let tracked = file.try_clone().ok();
+        let rdr = Self::new(ReaderKind::File(file));
+        match tracked {
+            Some(f) => rdr.track_file(f),
+            None => rdr,
+        }
     }
 
     pub fn from_stdin() -> Self {
-        Self::Stdin(stdin())
+        Self::new(ReaderKind::Stdin(stdin()))
     }
 
     pub fn from_child(mut c: Child) -> Self {
         let cs = c.stdout.take().expect("Erro getting child stdout");
-        Self::Child(c, cs)
+        Self::new(ReaderKind::Child(Some(c), cs))
     }
 
     pub fn from_pipe_reader(pr: PipeReader) -> Self {
-        Self::PipeReader(pr)
+        Self::new(ReaderKind::PipeReader(pr))
+    }
+
+    /// Returns the source of the reader
+    pub fn get_ref(&self) -> &ReaderKind {
+        &self.inner
+    }
+
+    /// Returns the source of the reader.  Reading directly from the source bypasses the
+    /// statistics, timeouts and cancellation of the reader.
+    pub fn get_mut(&mut self) -> &mut ReaderKind {
+        &mut self.inner
+    }
+
+    // Input file, used to count the compressed bytes read
+    pub(crate) fn track_file(mut self, file: File) -> Self {
+        self.stats.set_file(file);
+        self
+    }
+
+    pub(crate) fn with_stderr(mut self, stderr: Option<StderrCollector>) -> Self {
+        self.stderr = stderr;
+        self
+    }
+
+    fn with_pump(mut self, pump: JoinHandle<io::Result<()>>) -> Self {
+        self.pump = Some(pump);
+        self
+    }
+
+    // Waits for the thread copying the input (if any) to finish, returning any error from
+    // reading the source
+    fn join_pump(&mut self) -> io::Result<()> {
+        match self.pump.take() {
+            Some(h) => h
+                .join()
+                .unwrap_or_else(|_| Err(Error::other("Input thread panicked"))),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn set_progress(&mut self, progress: ProgressState) {
+        self.stats.set_progress(progress)
+    }
+
+    pub(crate) fn set_timeouts(&mut self, timeouts: &Timeouts) {
+        let pid = match &self.inner {
+            ReaderKind::Child(Some(c), _) => Some(c.id()),
+            _ => None,
+        };
+        self.guard = Some(Guard::new(timeouts, pid))
+    }
+
+    /// Returns the current statistics for the reader (see [`stats`](crate::stats)).  Note that
+    /// for compressed input the number of compressed bytes is the number of bytes consumed by
+    /// the decompression utility, which will normally be ahead of the data returned by the
+    /// reader.
+    pub fn stats(&self) -> IoStats {
+        self.stats.stats()
+    }
+
+    /// Finishes reading and, for a reader created from a child process, waits for the child
+    /// process to exit.  Any data that has not been read is discarded (although it is counted
+    /// in the returned statistics).  Unlike dropping the reader (which kills the child process),
+    /// this reports an error if the child process exits with a failure status, so can be used
+    /// to check that the input was decompressed successfully.  On success the final statistics
+    /// for the reader are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut rd = CompressIo::new().path("foo.gz").reader()?;
+    ///   let mut contents = String::new();
+    ///   rd.read_to_string(&mut contents)?;
+    ///   rd.finish()?;
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn finish(mut self) -> io::Result<IoStats> {
+        let mut cpu = None;
+        if matches!(self.inner, ReaderKind::Child(..)) || self.pump.is_some() {
+            io::copy(&mut self, &mut io::sink())?;
+        }
+        if let ReaderKind::Child(c, _) = &mut self.inner {
+            if let Some(child) = c.take() {
+                let (status, usage) = reap_child(self.guard.as_mut(), child)?;
+                let stderr = self.stderr.take().and_then(|s| s.finish());
+                check_status(status).map_err(|e| process::add_stderr(e, stderr))?;
+                cpu = Some(usage)
+            }
+        }
+        self.stats.finish(cpu);
+        Ok(self.stats())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Reader {
+    // File descriptor suitable for reading from directly. Stdin is excluded as it is buffered,
+    // and the buffer may already contain data
+    fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        match &self.inner {
+            ReaderKind::File(f) => Some(f.as_raw_fd()),
+            ReaderKind::Child(_, cs) => Some(cs.as_raw_fd()),
+            ReaderKind::PipeReader(pr) => Some(pr.as_raw_fd()),
+            ReaderKind::Stdin(_) => None,
+        }
     }
 }
 
+/// Copies the entire contents of `reader` into `writer`, returning the number of bytes copied.
+/// On Linux, if both ends are files or pipes (i.e., uncompressed files, or the input or output of a
+/// compression utility) the data is copied within the kernel using `splice(2)` or
+/// `copy_file_range(2)` without passing through userspace buffers.  Otherwise, or if the kernel
+/// does not support copying between the two ends, this falls back to [`io::copy`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use compress_io::compress::{self, CompressIo};
+///
+/// fn main() -> std::io::Result<()> {
+///   let mut rdr = CompressIo::new().path("foo.txt").reader()?;
+///   let mut wrt = CompressIo::new().path("foo.zst").writer()?;
+///   compress::copy(&mut rdr, &mut wrt)?;
+///   rdr.finish()?;
+///   wrt.finish()?;
+///   Ok(())
+/// }
+/// ```
+pub fn copy(reader: &mut Reader, writer: &mut Writer) -> io::Result<u64> {
+    // The fast path is not used with timeouts or cancellation
+    #[cfg(target_os = "linux")]
+    if let (None, None, Some(src), Some(dst)) = (
+        reader.guard.as_ref(),
+        writer.guard.as_ref(),
+        reader.raw_fd(),
+        writer.raw_fd(),
+    ) {
+        let (n, complete) = fast_copy::copy_fd(src, dst, |k| {
+            reader.stats.add(k);
+            writer.stats.add(k);
+        })?;
+        if complete {
+            // The input thread (if any) may have failed reading the source
+            reader.join_pump()?;
+            return Ok(n);
+        }
+        return io::copy(reader, writer).map(|m| n + m);
+    }
+    io::copy(reader, writer)
+}
+
 impl Read for Reader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            Self::File(f) => f.read(buf),
-            Self::Child(_, cs) => cs.read(buf),
-            Self::Stdin(s) => s.read(buf),
-            Self::PipeReader(pr) => pr.read(buf),
+        if let Some(g) = self.guard.as_ref() {
+            let res = match &self.inner {
+                ReaderKind::Child(_, cs) => g.wait_ready(cs.as_raw_fd(), false),
+                ReaderKind::PipeReader(pr) => g.wait_ready(pr.as_raw_fd(), false),
+                _ => g.check_cancelled(),
+            };
+            if let Err(e) = res {
+                if let ReaderKind::Child(Some(c), _) = &mut self.inner {
+                    let _ = c.kill();
+                }
+                return Err(e);
+            }
+        }
+        let n = match &mut self.inner {
+            ReaderKind::File(f) => f.read(buf),
+            ReaderKind::Child(_, cs) => cs.read(buf),
+            ReaderKind::Stdin(s) => s.read(buf),
+            ReaderKind::PipeReader(pr) => pr.read(buf),
+        }?;
+        // At the end of the input, report any error reading the source
+        if n == 0 && !buf.is_empty() {
+            self.join_pump()?
         }
+        if let Some(g) = self.guard.as_mut() {
+            g.activity()
+        }
+        self.stats.add(n);
+        Ok(n)
     }
 }
 
@@ -368,25 +1109,34 @@ impl Read for Reader {
 ///  }
 /// ```
 ///
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct CompressIo {
     path: Option<PathBuf>,
     ctype: CompressType,
     cthreads: CompressThreads,
+    level: Option<u32>,
     fix_path: bool,
     no_wait: bool,
+    progress: Option<ProgressHook>,
+    progress_interval: Option<Duration>,
+    #[cfg(feature = "progress-bar")]
+    progress_bar: bool,
+    timeouts: Timeouts,
+    spawn: SpawnOptions,
+    search_path: Option<Vec<PathBuf>>,
 }
 
 impl CompressIo {
     /// Creates a new instance of [`CompressIo`] wih the default arguments:
     /// * No associated file path so a reader will be connected to `stdin` and a writer to `stdout`
     /// * Compression set to [`CompressType::Unknown`] so a reader will use the first bytes
-    ///     from the file/stream and a writer will use the file path extension to determine the
-    ///     compression type to use.  Note that if the compression type can not be determined then
-    ///     no compression/decompression will be applied
+    ///   from the file/stream and a writer will use the file path extension to determine the
+    ///   compression type to use.  Note that if the compression type can not be determined then
+    ///   no compression/decompression will be applied
     /// * The file path for a writer will be modified by the addition of the suffix corresponding
-    ///     to the chosen compression format if necessary
-    /// * Default arguments will be used for threading of compression utilities
+    ///   to the chosen compression format if necessary
+    /// * Default arguments will be used for threading of compression utilities, unless
+    ///   overridden by the `COMPRESS_IO_THREADS` environment variable (see [`config`])
     ///
     /// # Examples
     ///
@@ -396,8 +1146,13 @@ impl CompressIo {
     /// // bytes from the stream
     /// let mut rd = CompressIo::new().reader();
     /// ```
+    ///
+    /// [`config`]: crate::config
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            cthreads: config::default_threads(),
+            ..Default::default()
+        }
     }
 
     /// Sets the file path associated with a reader or writer
@@ -503,6 +1258,26 @@ impl CompressIo {
         self
     }
 
+    /// Sets the compression level.  By default no level is given so each utility uses its
+    /// default level.  The meaning and the range of valid levels depends on the utility
+    /// (i.e., 1-9 for [gzip] and 1-19 for [zstd]); the level is ignored for utilities that do not
+    /// support setting the level.  Has no effect on readers.
+    ///
+    /// [gzip]: http://www.gzip.org/
+    /// [zstd]: https://facebook.github.io/zstd/
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    ///  use compress_io::compress::CompressIo;
+    ///  let mut wrt = CompressIo::new().path("foo.zst").level(19).writer()
+    ///    .expect("Error opening output file");
+    /// ```
+    pub fn level(&mut self, level: u32) -> &mut Self {
+        self.level = Some(level);
+        self
+    }
+
     /// Prevents the file path for writers being modified by the addition of a compression suffix.
     /// Has no effect on readers.  By default when a writer or bufwriter is generated (with
     /// [`CompressIo::writer`] or [`CompressIo::bufwriter`]) and if a file path has been set (with
@@ -538,6 +1313,11 @@ impl CompressIo {
     /// as the thread can block when the writer is dropped.  If this behaviour is not wanted then
     /// `no_wait()` can be used to prevent waiting on dropping.
     ///
+    /// The child process is waited on by a background thread, so it does not remain as a zombie
+    /// process after finishing.  [`Writer::completion`] returns a handle that can be used to
+    /// check when the child process has finished and whether it was successful, and
+    /// [`process::wait_background`] waits for all such child processes.
+    ///
     /// An alternative to `no_wait()` is to use [`Writer::take_child`] on the writer.  This returns
     /// the child process so that the calling thread can choose when to wait for the child process.
     ///
@@ -555,6 +1335,193 @@ impl CompressIo {
         self
     }
 
+    /// Registers a callback that will be called periodically with the progress of readers and
+    /// writers generated from this instance (see [`progress`](crate::progress)).  For file
+    /// inputs, the progress includes the number of compressed bytes consumed from the file and
+    /// the total size of the file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut rd = CompressIo::new()
+    ///     .path("foo.gz")
+    ///     .progress(|p| {
+    ///       if let Some(x) = p.fraction() {
+    ///         eprint!("\r{:.1}%", x * 100.0)
+    ///       }
+    ///     })
+    ///     .reader()?;
+    ///   let mut contents = String::new();
+    ///   rd.read_to_string(&mut contents)?;
+    ///   rd.finish()?;
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn progress<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        let callback: Arc<ProgressFn> = Arc::new(callback);
+        self.progress = Some(ProgressHook::new(callback));
+        #[cfg(feature = "progress-bar")]
+        {
+            self.progress_bar = false;
+        }
+        self
+    }
+
+    /// Sets the maximum time that a read or write can wait for the compression utility.  If the
+    /// timeout expires the child process is killed and the operation returns an error of kind
+    /// [`io::ErrorKind::TimedOut`] (see [`cancel`](crate::cancel)).
+    pub fn inactivity_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.inactivity = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum lifetime of readers and writers, including the time taken by
+    /// [`Reader::finish`] or [`Writer::finish`].  If the timeout expires the child process is
+    /// killed and operations return an error of kind [`io::ErrorKind::TimedOut`]
+    /// (see [`cancel`](crate::cancel)).
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeouts.total = Some(timeout);
+        self
+    }
+
+    /// Associates readers and writers with a [`CancelHandle`] so they can be cancelled from
+    /// another thread (see [`cancel`](crate::cancel))
+    pub fn cancel_handle(&mut self, handle: &CancelHandle) -> &mut Self {
+        self.timeouts.cancel = Some(handle.clone());
+        self
+    }
+
+    /// Sets whether child processes are killed if the thread creating the reader or writer
+    /// exits (which is not necessarily when the calling process exits).  Only has an effect on
+    /// Linux, and is ignored for writers created with [`CompressIo::no_wait`].  The default is
+    /// false unless changed with [`process::set_kill_on_parent_death`] (see
+    /// [`process`](crate::process)).
+    pub fn kill_on_parent_death(&mut self, x: bool) -> &mut Self {
+        self.spawn = self.spawn.clone().kill_on_parent_death(x);
+        self
+    }
+
+    /// Sets whether child processes are placed in their own process group, so that they do not
+    /// receive signals such as `SIGINT` from the terminal.  The default is false unless changed
+    /// with [`process::set_process_group`] (see [`process`](crate::process)).
+    pub fn process_group(&mut self, x: bool) -> &mut Self {
+        self.spawn = self.spawn.clone().process_group(x);
+        self
+    }
+
+    /// Sets how messages written to `stderr` by the compression utilities are handled (see
+    /// [`StderrPolicy`]).  By default they go to the `stderr` of the calling process.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Read;
+    /// use compress_io::{compress::CompressIo, process::StderrPolicy};
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   // Include up to 4KB of error messages from the decompression utility in the error
+    ///   // returned from finish() if the utility fails
+    ///   let mut rd = CompressIo::new()
+    ///     .path("foo.gz")
+    ///     .stderr(StderrPolicy::Capture(4096))
+    ///     .reader()?;
+    ///   let mut contents = String::new();
+    ///   rd.read_to_string(&mut contents)?;
+    ///   rd.finish()?;
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn stderr(&mut self, policy: StderrPolicy) -> &mut Self {
+        self.spawn = self.spawn.clone().stderr(policy);
+        self
+    }
+
+    /// Sets the resource controls (nice level, I/O priority, CPU affinity and resource limits)
+    /// for the compression utilities (see [`Resources`]).  This replaces any default set with
+    /// [`process::set_default_resources`].
+    pub fn resources(&mut self, resources: Resources) -> &mut Self {
+        self.spawn = self.spawn.clone().resources(resources);
+        self
+    }
+
+    /// Searches for the compression utilities in `dirs` (in the order given) rather than in
+    /// `$PATH`.  This only affects readers and writers created from this instance; to pick up
+    /// changes to `$PATH` or newly installed utilities globally use [`tools::rescan`].  Any
+    /// changes to the tools from the configuration files or environment are still applied.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// let mut wrt = CompressIo::new()
+    ///   .path("foo.zst")
+    ///   .search_path(["/opt/zstd/bin", "/usr/bin"])
+    ///   .writer()
+    ///   .expect("Error opening output file");
+    /// ```
+    pub fn search_path<I, P>(&mut self, dirs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.search_path = Some(dirs.into_iter().map(|p| p.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Sets the minimum interval between calls to the progress callback (default 500ms)
+    pub fn progress_interval(&mut self, interval: Duration) -> &mut Self {
+        self.progress_interval = Some(interval);
+        self
+    }
+
+    /// Displays a progress bar on `stderr` for readers and writers generated from this instance
+    /// (see [`progress`](crate::progress)).  This replaces any callback set with
+    /// [`CompressIo::progress`].
+    ///
+    /// *Only available with the `progress-bar` feature*
+    #[cfg(feature = "progress-bar")]
+    pub fn progress_bar(&mut self) -> &mut Self {
+        self.progress = None;
+        self.progress_bar = true;
+        self
+    }
+
+    // The total size of the input is only found if there is a progress callback
+    #[cfg_attr(not(feature = "progress-bar"), allow(unused_variables))]
+    fn progress_state<F>(&self, filter: &Filter, stream: &str, total: F) -> Option<ProgressState>
+    where
+        F: FnOnce() -> Option<u64>,
+    {
+        let (mut hook, total) = match self.progress.as_ref() {
+            Some(hook) => (hook.clone(), total()),
+            #[cfg(feature = "progress-bar")]
+            None if self.progress_bar => {
+                let total = total();
+                let name = self
+                    .path
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| stream.to_owned());
+                let bar = crate::progress::new_bar(filter.tool_name(), &name, total);
+                let callback: Arc<ProgressFn> = Arc::new(crate::progress::bar_callback(bar));
+                (ProgressHook::new(callback), total)
+            }
+            None => return None,
+        };
+        if let Some(d) = self.progress_interval {
+            hook.set_interval(d)
+        }
+        Some(ProgressState::new(hook, total))
+    }
+
     /// Generates a [`Read`] instance using the supplied settings.  This will return [`io::Error`]
     /// on failure which could be due to various reasons such as the source file not existing or
     /// not being accessible, or a suitable utility for decompressing not being available in the
@@ -576,12 +1543,80 @@ impl CompressIo {
     /// ```
     pub fn reader(&self) -> io::Result<Reader> {
         let mut buf = CheckBuf::default();
-        let filter = Filter::new_decompress_filter(check_read_ctype(
+        let filter = self.decompress_filter(check_read_ctype(
             self.path.as_ref(),
             self.ctype,
             Some(&mut buf),
         )?)?;
-        filter.reader(self.path.as_ref(), buf)
+        self.open_reader(&filter, buf)
+    }
+
+    // Generates a reader for `filter`, where `buf` holds any bytes already read from stdin
+    fn open_reader(&self, filter: &Filter, buf: CheckBuf) -> io::Result<Reader> {
+        let mut rdr = filter.reader(self.path.as_ref(), buf)?;
+        let total = || {
+            self.path
+                .as_ref()
+                .and_then(|p| std::fs::metadata(p).ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+        };
+        if let Some(p) = self.progress_state(filter, "stdin", total) {
+            rdr.set_progress(p)
+        }
+        if self.timeouts.is_set() {
+            rdr.set_timeouts(&self.timeouts)
+        }
+        Ok(rdr)
+    }
+
+    /// Generates a [`Read`] instance decompressing the data read from `src` (for example, a
+    /// network stream or an in-memory buffer) rather than from the file path or `stdin`.  Unless
+    /// the compression type has been set, it is determined from the first bytes read from
+    /// `src`.  The data is copied from `src` to the decompression utility by a separate thread;
+    /// an error reading from `src` is returned from the reader (and from [`Reader::finish`])
+    /// after the data preceding the error.  If the decompression utility exits before reading
+    /// all of its input, the end of the reader is delayed until the pending read from `src`
+    /// completes.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::{io::Read, net::TcpStream};
+    /// use compress_io::compress::CompressIo;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let stream = TcpStream::connect("127.0.0.1:8080")?;
+    ///   let mut rd = CompressIo::new().reader_from(stream)?;
+    ///   let mut contents = String::new();
+    ///   rd.read_to_string(&mut contents)?;
+    ///   rd.finish()?;
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn reader_from<R: Read + Send + 'static>(&self, mut src: R) -> io::Result<Reader> {
+        let mut head = Vec::new();
+        let ctype = if self.ctype == CompressType::Unknown {
+            (&mut src).take(6).read_to_end(&mut head)?;
+            let ct = if head.len() == 6 {
+                get_ctype(&head)
+            } else {
+                CompressType::NoFilter
+            };
+            trace!("Detected compression type {} for source", ct);
+            ct
+        } else {
+            self.ctype
+        };
+        let filter = self.decompress_filter(ctype)?;
+        let mut rdr = filter.reader_from(head, src)?;
+        if let Some(p) = self.progress_state(&filter, "input", || None) {
+            rdr.set_progress(p)
+        }
+        if self.timeouts.is_set() {
+            rdr.set_timeouts(&self.timeouts)
+        }
+        Ok(rdr)
     }
 
     /// Generates a [`BufReader`] instance using the supplied settings.  This will return [`io::Error`]
@@ -625,7 +1660,30 @@ impl CompressIo {
     /// }
     /// ```
     pub fn writer(&self) -> io::Result<Writer> {
-        let ctype = if self.ctype == CompressType::Unknown {
+        self.writer_with_path().map(|(w, _)| w)
+    }
+
+    pub(crate) fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Generates a writer, also returning the output path after any compression suffix was added
+    pub(crate) fn writer_with_path(&self) -> io::Result<(Writer, Option<PathBuf>)> {
+        let filter = self.compress_filter()?;
+        let path = filter.output_path(self.path.as_ref(), self.fix_path);
+        let mut wrt = filter.writer(path.as_ref(), true, self.no_wait)?;
+        if let Some(p) = self.progress_state(&filter, "stdout", || None) {
+            wrt.set_progress(p)
+        }
+        if self.timeouts.is_set() {
+            wrt.set_timeouts(&self.timeouts)
+        }
+        Ok((wrt, path))
+    }
+
+    // Compression type for writing, from the file suffix if not set explicitly
+    fn write_ctype(&self) -> CompressType {
+        if self.ctype == CompressType::Unknown {
             if let Some(p) = self.path.as_ref() {
                 CompressType::from_suffix(p)
             } else {
@@ -633,9 +1691,56 @@ impl CompressIo {
             }
         } else {
             self.ctype
-        };
-        let filter = Filter::new_compress_filter(ctype, self.cthreads)?;
-        filter.writer(self.path.as_ref(), self.fix_path, self.no_wait)
+        }
+    }
+
+    fn compress_filter(&self) -> io::Result<Filter> {
+        Filter::compress_filter_in(&self.tools(), self.write_ctype(), self.cthreads, self.level)
+            .map(|f| f.with_spawn_options(self.spawn.clone()))
+    }
+
+    fn decompress_filter(&self, ctype: CompressType) -> io::Result<Filter> {
+        Filter::decompress_filter_in(&self.tools(), ctype)
+            .map(|f| f.with_spawn_options(self.spawn.clone()))
+    }
+
+    // Tool register for the search path
+    fn tools(&self) -> Arc<ToolRegister> {
+        tools::registry_for(self.search_path.as_deref())
+    }
+
+    /// Generates a [`SinkWriter`] sending the compressed output to `sink` (for example, a
+    /// `Vec<u8>` or a network stream) rather than to a file or `stdout`.  The compression type is
+    /// taken from [`CompressIo::ctype`] or, if not set, from the suffix of the path (no file is
+    /// created).  If neither is set the data is written to `sink` uncompressed.  The output is
+    /// copied into `sink` by a separate thread; [`SinkWriter::finish`] returns the sink once
+    /// all of the output has been copied, and reports errors from both the compression utility
+    /// and writing to the sink.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    /// use compress_io::{compress::CompressIo, compress_type::CompressType};
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///   let mut wrt = CompressIo::new().ctype(CompressType::Zstd).writer_into(Vec::new())?;
+    ///   writeln!(wrt, "Hello world")?;
+    ///   let compressed = wrt.finish()?;
+    ///   println!("{} bytes of compressed output", compressed.len());
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn writer_into<W: Write + Send + 'static>(&self, sink: W) -> io::Result<SinkWriter<W>> {
+        let filter = self.compress_filter()?;
+        let mut wrt = filter.writer_into(sink)?;
+        if let Some(p) = self.progress_state(&filter, "output", || None) {
+            wrt.writer.set_progress(p)
+        }
+        if self.timeouts.is_set() {
+            wrt.writer.set_timeouts(&self.timeouts)
+        }
+        Ok(wrt)
     }
 
     /// Generates a [`BufWriter'] instance using the supplied settings.  This will return
@@ -659,3 +1764,152 @@ impl CompressIo {
         self.writer().map( BufWriter::new)
     }
 }
+
+/// Exit statuses of the child processes from [`transcode`].  A status is `None` if no child
+/// process was required for that step (i.e., the input was not compressed).  If the
+/// [`StderrPolicy::Capture`] policy was used, the output captured from the `stderr` of the child
+/// processes is also given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscodeStatus {
+    pub decompress: Option<ExitStatus>,
+    pub compress: Option<ExitStatus>,
+    pub decompress_stderr: Option<String>,
+    pub compress_stderr: Option<String>,
+}
+
+impl TranscodeStatus {
+    /// Returns true if all child processes exited successfully
+    pub fn success(&self) -> bool {
+        [self.decompress, self.compress]
+            .iter()
+            .flatten()
+            .all(|s| s.success())
+    }
+
+    /// Returns an error if either child process failed
+    pub fn check(&self) -> io::Result<()> {
+        for (s, stderr, step) in [
+            (self.decompress, &self.decompress_stderr, "Decompression"),
+            (self.compress, &self.compress_stderr, "Compression"),
+        ] {
+            if let Some(s) = s.filter(|s| !s.success()) {
+                let e = Error::other(format!("{} failed: {}", step, s));
+                return Err(process::add_stderr(e, stderr.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recompresses the input described by `src` to the output described by `dst`.  The
+/// decompression utility writes directly into the compression utility through a pipe, so
+/// the data does not pass through the calling process.  The input compression type is
+/// detected as for [`CompressIo::reader`], and the output compression type and file name are
+/// determined as for [`CompressIo::writer`].  Both child processes are waited on, and their
+/// exit statuses returned; use [`TranscodeStatus::check`] to convert a failure into an error.
+/// If a utility can not be started then no output file is left behind.
+///
+/// # Examples
+///
+/// ```no_run
+/// use compress_io::compress::{transcode, CompressIo};
+///
+/// fn main() -> std::io::Result<()> {
+///   let status = transcode(CompressIo::new().path("foo.gz"), CompressIo::new().path("foo.zst"))?;
+///   status.check()
+/// }
+/// ```
+pub fn transcode(src: &CompressIo, dst: &CompressIo) -> io::Result<TranscodeStatus> {
+    let mut buf = CheckBuf::default();
+    let dec = src.decompress_filter(check_read_ctype(
+        src.path.as_ref(),
+        src.ctype,
+        Some(&mut buf),
+    )?)?;
+    let enc = dst.compress_filter()?;
+
+    let (dec, enc) = match (dec, enc) {
+        (Filter::NoFilter, Filter::NoFilter) => {
+            // Nothing to transcode, so just copy.  The bytes already read from stdin (if any)
+            // are in buf
+            let mut rdr = src.open_reader(&Filter::NoFilter, buf)?;
+            let mut wrt = dst.writer()?;
+            copy(&mut rdr, &mut wrt)?;
+            wrt.finish()?;
+            return Ok(TranscodeStatus {
+                decompress: None,
+                compress: None,
+                decompress_stderr: None,
+                compress_stderr: None,
+            });
+        }
+        x => x,
+    };
+
+    let input: Stdio = match src.path.as_ref() {
+        Some(p) => File::open(p)?.into(),
+        None if buf.is_empty() => Stdio::inherit(),
+        None => piped_stdin(buf).into(),
+    };
+    let out_path = enc.output_path(dst.path.as_ref(), dst.fix_path);
+    let output: Stdio = match out_path.as_ref() {
+        Some(p) => File::create(p)?.into(),
+        None => Stdio::inherit(),
+    };
+
+    let spawned = match (&dec, &enc) {
+        (Filter::Filter(d), Filter::Filter(c)) => {
+            spawn_tracked(d, input, Stdio::piped()).and_then(|(mut dchild, dstderr)| {
+                let pipe = dchild.stdout.take().expect("Error getting child stdout");
+                match spawn_tracked(c, pipe.into(), output) {
+                    Ok(cchild) => Ok((Some((dchild, dstderr)), Some(cchild))),
+                    Err(e) => {
+                        let _ = dchild.kill();
+                        let _ = process::wait(&mut dchild);
+                        Err(e)
+                    }
+                }
+            })
+        }
+        (Filter::Filter(d), Filter::NoFilter) => {
+            spawn_tracked(d, input, output).map(|c| (Some(c), None))
+        }
+        (Filter::NoFilter, Filter::Filter(c)) => {
+            spawn_tracked(c, input, output).map(|c| (None, Some(c)))
+        }
+        (Filter::NoFilter, Filter::NoFilter) => unreachable!(),
+    };
+    let (dchild, cchild) = match spawned {
+        Ok(x) => x,
+        Err(e) => {
+            // Nothing has been written, so do not leave an empty output file
+            if let Some(p) = out_path {
+                let _ = std::fs::remove_file(p);
+            }
+            return Err(e);
+        }
+    };
+    let wait = |(mut child, stderr): (Child, Option<StderrCollector>)| {
+        process::wait(&mut child).map(|s| (s, stderr.and_then(|e| e.finish())))
+    };
+    // Both children are waited on before any error is returned.  If waiting on the compressor
+    // fails the decompressor is killed, as it could otherwise block writing to the pipe.
+    let cres = cchild.map(wait).transpose();
+    let dres = dchild
+        .map(|mut d| {
+            if cres.is_err() {
+                let _ = d.0.kill();
+            }
+            d
+        })
+        .map(wait)
+        .transpose();
+    let (compress, compress_stderr) = cres?.unzip();
+    let (decompress, decompress_stderr) = dres?.unzip();
+    Ok(TranscodeStatus {
+        decompress,
+        compress,
+        decompress_stderr: decompress_stderr.flatten(),
+        compress_stderr: compress_stderr.flatten(),
+    })
+}
@@ -0,0 +1,95 @@
+//! Copying between file descriptors within the kernel using `copy_file_range(2)` (file to file)
+//! or `splice(2)` (where at least one end is a pipe).
+
+use std::{
+    io::{self, Error},
+    os::unix::io::RawFd,
+};
+
+// Maximum number of bytes to request from a single system call
+const CHUNK_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FdKind {
+    File,
+    Pipe,
+    Other,
+}
+
+fn fd_kind(fd: RawFd) -> FdKind {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut st) } != 0 {
+        return FdKind::Other;
+    }
+    match st.st_mode & libc::S_IFMT {
+        libc::S_IFREG => FdKind::File,
+        libc::S_IFIFO => FdKind::Pipe,
+        _ => FdKind::Other,
+    }
+}
+
+/// Copies from `src` to `dst` until end of file using `copy_file_range` or `splice`.  Returns
+/// the number of bytes copied and whether the copy completed.  If the kernel can not copy
+/// between the descriptors then the copy is incomplete and the remaining data should be copied
+/// through userspace.  As the file offsets are updated by the kernel this can happen at any
+/// point in the copy.  `copied` is called with the number of bytes copied by each system call.
+pub(super) fn copy_fd<F: FnMut(usize)>(
+    src: RawFd,
+    dst: RawFd,
+    mut copied: F,
+) -> io::Result<(u64, bool)> {
+    let (sk, dk) = (fd_kind(src), fd_kind(dst));
+    let use_splice = match (sk, dk) {
+        (FdKind::File, FdKind::File) => false,
+        (FdKind::Pipe, FdKind::File | FdKind::Pipe) | (FdKind::File, FdKind::Pipe) => true,
+        _ => return Ok((0, false)),
+    };
+    let mut written = 0;
+    loop {
+        let res = unsafe {
+            if use_splice {
+                libc::splice(
+                    src,
+                    std::ptr::null_mut(),
+                    dst,
+                    std::ptr::null_mut(),
+                    CHUNK_SIZE,
+                    libc::SPLICE_F_MOVE,
+                )
+            } else {
+                libc::copy_file_range(
+                    src,
+                    std::ptr::null_mut(),
+                    dst,
+                    std::ptr::null_mut(),
+                    CHUNK_SIZE,
+                    0,
+                )
+            }
+        };
+        match res {
+            // Some special files report a size of zero, so at the start we can not tell whether
+            // this is really the end of the file
+            0 => return Ok((written, written > 0 || use_splice)),
+            n if n > 0 => {
+                written += n as u64;
+                copied(n as usize)
+            }
+            _ => {
+                let e = Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => (),
+                    Some(
+                        libc::ENOSYS
+                        | libc::EXDEV
+                        | libc::EINVAL
+                        | libc::EPERM
+                        | libc::EOPNOTSUPP
+                        | libc::EBADF,
+                    ) => return Ok((written, false)),
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+}
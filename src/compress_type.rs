@@ -1,26 +1,21 @@
 use std::{
     fmt,
-    io::{self, Error, ErrorKind},
+    io::{self, Error},
     path::Path,
     str::FromStr,
 };
 
 use crate::tools::*;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
 pub enum CompressThreads {
+    #[default]
     Default,
     Set(usize),
     NCores,
     NPhysCores,
 }
 
-impl Default for CompressThreads {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 impl fmt::Display for CompressThreads {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -32,6 +27,22 @@ impl fmt::Display for CompressThreads {
     }
 }
 
+impl FromStr for CompressThreads {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "default" => Ok(Self::Default),
+            "ncores" => Ok(Self::NCores),
+            "nphyscores" => Ok(Self::NPhysCores),
+            s => s
+                .parse::<usize>()
+                .map(Self::Set)
+                .map_err(|_| "Illegal thread specification"),
+        }
+    }
+}
+
 impl CompressThreads {
     pub fn new() -> Self {
         Self::default()
@@ -47,7 +58,7 @@ impl CompressThreads {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub enum CompressType {
     Gzip,
     Bgzip,
@@ -58,15 +69,10 @@ pub enum CompressType {
     Lzma,
     Zstd,
     NoFilter,
+    #[default]
     Unknown,
 }
 
-impl Default for CompressType {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 impl fmt::Display for CompressType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -92,9 +98,10 @@ impl FromStr for CompressType {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.to_ascii_uppercase();
+        let s = s.to_ascii_lowercase();
         match s.as_str() {
             "gzip" => Ok(Self::Gzip),
+            "compress" => Ok(Self::Compress),
             "bzip2" => Ok(Self::Bzip2),
             "bgzip" => Ok(Self::Bgzip),
             "xz" => Ok(Self::Xz),
@@ -107,6 +114,21 @@ impl FromStr for CompressType {
 }
 
 impl CompressType {
+    /// All compression formats (i.e., excluding [`CompressType::NoFilter`] and
+    /// [`CompressType::Unknown`])
+    pub fn formats() -> &'static [CompressType] {
+        &[
+            Self::Gzip,
+            Self::Bgzip,
+            Self::Compress,
+            Self::Bzip2,
+            Self::Xz,
+            Self::Lz4,
+            Self::Lzma,
+            Self::Zstd,
+        ]
+    }
+
     pub fn from_suffix<P: AsRef<Path>>(path: P) -> Self {
         match path.as_ref().extension().and_then(|ext| ext.to_str()) {
             Some("gz") => Self::Gzip,
@@ -134,23 +156,42 @@ impl CompressType {
         }
     }
 
-    pub fn get_decompress_tool(&self) -> io::Result<&Tool> {
-        get_decompress_tool(*self).ok_or_else(|| {
-            Error::new(
-                ErrorKind::Other,
-                format!("Can not find program to decompress {} files", self),
-            )
-        })
+    /// The tool that would be used to decompress this type
+    pub fn decompress_tool(&self) -> io::Result<Tool> {
+        self.decompress_tool_in(&registry()).cloned()
+    }
+
+    /// The tool that would be used to compress to this type
+    pub fn compress_tool(&self) -> io::Result<Tool> {
+        self.compress_tool_in(&registry()).cloned()
     }
 
-    pub fn get_compress_tool(&self) -> io::Result<&Tool> {
-        get_compress_tool(*self).ok_or_else(|| {
-            Error::new(
-                ErrorKind::Other,
-                format!("Can not find program to compress {} files", self),
-            )
+    /// The tool that would be used to decompress this type (see
+    /// [`tools::get_decompress_tool`](crate::tools::get_decompress_tool))
+    #[deprecated(since = "0.7.0", note = "use `decompress_tool`")]
+    pub fn get_decompress_tool(&self) -> io::Result<&'static Tool> {
+        self.decompress_tool_in(static_registry())
+    }
+
+    /// The tool that would be used to compress to this type (see
+    /// [`tools::get_compress_tool`](crate::tools::get_compress_tool))
+    #[deprecated(since = "0.7.0", note = "use `compress_tool`")]
+    pub fn get_compress_tool(&self) -> io::Result<&'static Tool> {
+        self.compress_tool_in(static_registry())
+    }
+
+    // As get_decompress_tool() but selecting from `reg`
+    pub(crate) fn decompress_tool_in<'a>(&self, reg: &'a ToolRegister) -> io::Result<&'a Tool> {
+        reg.get_decompress_tool(*self).ok_or_else(|| {
+            Error::other(format!("Can not find program to decompress {} files", self))
         })
     }
+
+    // As get_compress_tool() but selecting from `reg`
+    pub(crate) fn compress_tool_in<'a>(&self, reg: &'a ToolRegister) -> io::Result<&'a Tool> {
+        reg.get_compress_tool(*self)
+            .ok_or_else(|| Error::other(format!("Can not find program to compress {} files", self)))
+    }
 }
 
 /// Guess file type if possible by reading first 6 bytes and looking for magic numbers
@@ -0,0 +1,202 @@
+//! Reading several (possibly compressed) files as one continuous stream
+//!
+//! A [`ConcatReader`] takes a list of paths and reads them in order as if they were a single
+//! file.  The compression format of each file is detected separately, so the files can be
+//! in different formats (or uncompressed).  Files are only opened (and any decompression
+//! utility started) when the preceding file has been completely read.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::BufRead;
+//! use compress_io::concat::ConcatReader;
+//!
+//! fn main() -> std::io::Result<()> {
+//!   let mut rdr = ConcatReader::new(["chunk1.gz", "chunk2.zst", "chunk3.txt"]);
+//!   let mut line = String::new();
+//!   loop {
+//!     line.clear();
+//!     match rdr.read_line(&mut line) {
+//!       Ok(0) => break,
+//!       Ok(_) => print!("{}", line),
+//!       Err(e) => {
+//!         eprintln!("Error reading {}: {}", rdr.position(), e);
+//!         return Err(e)
+//!       }
+//!     }
+//!   }
+//!   Ok(())
+//! }
+//! ```
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::compress::{CompressIo, Reader};
+
+/// A reader presenting several files as one continuous stream.  See the
+/// [module level documentation](crate::concat) for more details.
+///
+/// When the end of each file is reached, the reader is finished (see [`Reader::finish`]) so that
+/// a failure of the decompression utility is reported as an error.  After an error when opening
+/// or finishing a file, [`ConcatReader::position`] refers to that file, and subsequent reads
+/// continue with the next file.
+#[derive(Debug)]
+pub struct ConcatReader {
+    cfg: CompressIo,
+    paths: Vec<PathBuf>,
+    ix: usize,
+    opened: bool,
+    current: Option<BufReader<Reader>>,
+    offset: u64,
+    total: u64,
+}
+
+impl ConcatReader {
+    /// Creates a reader for the files in `paths` using the default [`CompressIo`] settings, so
+    /// the compression format of each file is detected automatically
+    pub fn new<I, P>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Self::with_config(&CompressIo::new(), paths)
+    }
+
+    /// Creates a reader for the files in `paths` using the settings from `cfg` (apart from the
+    /// path) for each file
+    pub fn with_config<I, P>(cfg: &CompressIo, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Self {
+            cfg: cfg.clone(),
+            paths: paths.into_iter().map(|p| p.as_ref().to_owned()).collect(),
+            ix: 0,
+            opened: false,
+            current: None,
+            offset: 0,
+            total: 0,
+        }
+    }
+
+    /// Index of the current file in the list of paths.  Equal to the number of files once
+    /// all files have been read.
+    pub fn file_index(&self) -> usize {
+        self.ix
+    }
+
+    /// Path of the current file, or `None` once all files have been read
+    pub fn path(&self) -> Option<&Path> {
+        self.paths.get(self.ix).map(|p| p.as_path())
+    }
+
+    /// Number of (uncompressed) bytes read from the current file
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of (uncompressed) bytes read from all files
+    pub fn total_offset(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the current position, which implements [`fmt::Display`] for use in error messages
+    pub fn position(&self) -> Position<'_> {
+        Position {
+            path: self.path(),
+            file_index: self.ix,
+            files: self.paths.len(),
+            offset: self.offset,
+        }
+    }
+
+    // Move to the next file with data available, or to the end of the list of files
+    fn advance(&mut self) -> io::Result<()> {
+        loop {
+            if let Some(rdr) = self.current.as_mut() {
+                if !rdr.fill_buf()?.is_empty() {
+                    break;
+                }
+                let rdr = self.current.take().unwrap();
+                rdr.into_inner().finish()?;
+                continue;
+            }
+            if self.opened {
+                self.ix += 1;
+                self.offset = 0;
+                self.opened = false;
+            }
+            match self.paths.get(self.ix) {
+                Some(p) => {
+                    self.opened = true;
+                    let rdr = self.cfg.clone().path(p).reader()?;
+                    self.current = Some(BufReader::new(rdr));
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for ConcatReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let mut rem = self.fill_buf()?;
+            rem.read(buf)?
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ConcatReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.advance()?;
+        match self.current.as_mut() {
+            Some(rdr) => rdr.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(rdr) = self.current.as_mut() {
+            rdr.consume(amt);
+            self.offset += amt as u64;
+            self.total += amt as u64;
+        }
+    }
+}
+
+/// The position of a [`ConcatReader`], returned by [`ConcatReader::position`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Position<'a> {
+    /// Path of the current file (`None` at the end of the input)
+    pub path: Option<&'a Path>,
+    pub file_index: usize,
+    /// Total number of files
+    pub files: usize,
+    /// Uncompressed offset in the current file
+    pub offset: u64,
+}
+
+impl fmt::Display for Position<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(p) => write!(
+                f,
+                "{} (file {} of {}) at offset {}",
+                p.display(),
+                self.file_index + 1,
+                self.files,
+                self.offset
+            ),
+            None => write!(f, "end of input"),
+        }
+    }
+}
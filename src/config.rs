@@ -0,0 +1,358 @@
+//! Configuration of `compress_io` from configuration files and the environment.
+//!
+//! ## Environment variables
+//!
+//! The following environment variables are read when the tool register is first used (tool
+//! preferences) or when a new [`CompressIo`] is created (threads).  Invalid entries are ignored,
+//! with invalid compression formats being recorded in [`config_warnings`].
+//! Settings from the environment take precedence over those from configuration files.
+//!
+//! | Variable | Example | Effect |
+//! |----------|---------|--------|
+//! | `COMPRESS_IO_<FORMAT>` | `COMPRESS_IO_GZIP=igzip,pigz` | Tools to prefer (in order) for compressing and decompressing `<FORMAT>` |
+//! | `COMPRESS_IO_DISABLE` | `COMPRESS_IO_DISABLE=pigz,xz:zstd` | Tools that should never be used, either for all formats (`tool`) or for a single format (`format:tool`) |
+//! | `COMPRESS_IO_THREADS` | `COMPRESS_IO_THREADS=8` | Default [`CompressThreads`]: `default`, `ncores`, `nphyscores` or a number of threads |
+//!
+//! `<FORMAT>` is the upper case name of a [`CompressType`] (`GZIP`, `BGZIP`, `COMPRESS`,
+//! `BZIP2`, `XZ`, `LZ4`, `LZMA` or `ZSTD`).  Preferences and exclusions only affect the selection
+//! between tools; they can not make a tool available that is not in the user's `$PATH`.
+//!
+//! ## Configuration files
+//!
+//! With the `config-file` feature (enabled by default), additional tools can be declared, and
+//! built-in tools modified, in [TOML] configuration files.  The following files are read in order
+//! when the tool register is first used, with later files taking precedence:
+//!
+//! * `/etc/compress_io/tools.toml`
+//! * `$XDG_CONFIG_HOME/compress_io/tools.toml` (or `$HOME/.config/compress_io/tools.toml`)
+//!
+//! If `COMPRESS_IO_CONFIG` is set then only the file it names is read.  In [hardened mode] only
+//! `/etc/compress_io/tools.toml` is read, and the tool preferences and exclusions given by
+//! environment variables are ignored, as these are under the control of the user.
+//!
+//! Each tool is given by a
+//! `[[tool]]` table.  A tool with the same name as an existing tool replaces it if any services
+//! are declared, otherwise only the program path of the existing tool is changed.
+//!
+//! ```toml
+//! # Use a site specific build of zstd, keeping the built-in services
+//! [[tool]]
+//! name = "zstd"
+//! path = "/opt/zstd-1.5/bin/zstd"
+//!
+//! # Add a new tool
+//! [[tool]]
+//! name = "igzip"
+//! path = "/opt/isa-l/bin/igzip"
+//!
+//! [[tool.compress]]
+//! format = "gzip"
+//! options = ["-c"]
+//! threads = "-T {n}"
+//! level = "-{n}"
+//! priority = 20
+//!
+//! [[tool.decompress]]
+//! format = "gzip"
+//! options = ["-dc", { env = "TMPDIR", value = "/scratch" }]
+//! priority = 20
+//! ```
+//!
+//! Services can also give `min_version` and `thread_min_version` (i.e., `"5.2"`) and a list of
+//! capabilities in `requires` (see [`Service`]), and tools can give `version_args` and
+//! `help_args` for probing the installed tool.
+//!
+//! `options`, `threads`, `all_cores` and `level` take option templates as described for [`ToolOpt`],
+//! either as a string of command line arguments or as an `{ env, value }` table to set an
+//! environment variable.  Errors in configuration files do not prevent the library from
+//! working; the file in question is skipped and the problem recorded in [`config_warnings`].
+//!
+//! [`CompressIo`]: crate::compress::CompressIo
+//! [hardened mode]: crate::hardened
+//! [`ToolOpt`]: crate::tools::ToolOpt
+//! [`Service`]: crate::tools::Service
+//! [`config_warnings`]: crate::tools::config_warnings
+//! [TOML]: https://toml.io
+
+use std::env;
+#[cfg(feature = "config-file")]
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "config-file")]
+use serde::Deserialize;
+
+use crate::compress_type::{CompressThreads, CompressType};
+use crate::hardened;
+use crate::tools::ToolRegister;
+#[cfg(feature = "config-file")]
+use crate::tools::{Service, Tool, ToolOpt, ToolVersion};
+
+/// Prefix for environment variables used by `compress_io`
+pub const ENV_PREFIX: &str = "COMPRESS_IO_";
+
+/// Environment variable listing tools that should not be used
+pub const ENV_DISABLE: &str = "COMPRESS_IO_DISABLE";
+
+/// Environment variable giving the default [`CompressThreads`]
+pub const ENV_THREADS: &str = "COMPRESS_IO_THREADS";
+
+/// Environment variable giving a configuration file to be used instead of the default files
+pub const ENV_CONFIG: &str = "COMPRESS_IO_CONFIG";
+
+#[cfg(feature = "config-file")]
+const SYSTEM_CONFIG: &str = "/etc/compress_io/tools.toml";
+
+/// Name of the environment variable giving the preferred tools for `ct`
+pub fn env_var_name(ct: CompressType) -> String {
+    format!("{}{}", ENV_PREFIX, ct.to_string().to_ascii_uppercase())
+}
+
+fn env_list(var: &str) -> Vec<String> {
+    env::var(var)
+        .map(|s| {
+            s.split(',')
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Default threading options, taken from `COMPRESS_IO_THREADS` if set
+pub fn default_threads() -> CompressThreads {
+    env::var(ENV_THREADS)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+/// Apply configuration files (if enabled) and then the environment to a tool register.  In
+/// hardened mode only the system wide configuration file is used
+pub(crate) fn configure(reg: &mut ToolRegister) {
+    #[cfg(feature = "config-file")]
+    apply_config_files(reg);
+    if !hardened::is_enabled() {
+        apply_env(reg)
+    }
+}
+
+/// Apply tool preferences and exclusions from the environment to a tool register
+fn apply_env(reg: &mut ToolRegister) {
+    for ct in CompressType::formats() {
+        let v = env_list(&env_var_name(*ct));
+        if !v.is_empty() {
+            reg.prefer(*ct, &v)
+        }
+    }
+    for s in env_list(ENV_DISABLE) {
+        match s.split_once(':') {
+            Some((ct, tool)) => match ct.trim().parse() {
+                Ok(ct) => reg.disable(tool.trim(), Some(ct)),
+                Err(_) => reg.warn(format!(
+                    "Unknown compression format in {}: {}",
+                    ENV_DISABLE, s
+                )),
+            },
+            None => reg.disable(&s, None),
+        }
+    }
+}
+
+/// Configuration files to be read, in order
+#[cfg(feature = "config-file")]
+pub fn config_files() -> Vec<PathBuf> {
+    let mut v = vec![PathBuf::from(SYSTEM_CONFIG)];
+    if hardened::is_enabled() {
+        return v;
+    }
+    if let Some(p) = env::var_os(ENV_CONFIG) {
+        return vec![PathBuf::from(p)];
+    }
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")));
+    if let Some(d) = dir {
+        v.push(d.join("compress_io").join("tools.toml"))
+    }
+    v
+}
+
+#[cfg(feature = "config-file")]
+fn apply_config_files(reg: &mut ToolRegister) {
+    let explicit = !hardened::is_enabled() && env::var_os(ENV_CONFIG).is_some();
+    for p in config_files() {
+        match apply_config_file(reg, &p) {
+            Err(e) if e.kind() == ErrorKind::NotFound && !explicit => (),
+            Err(e) => reg.warn(format!("Error reading {}: {}", p.display(), e)),
+            Ok(_) => (),
+        }
+    }
+}
+
+#[cfg(feature = "config-file")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    tool: Vec<ToolConfig>,
+}
+
+#[cfg(feature = "config-file")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToolConfig {
+    name: String,
+    path: Option<PathBuf>,
+    version_args: Option<String>,
+    help_args: Option<String>,
+    #[serde(default)]
+    compress: Vec<ServiceConfig>,
+    #[serde(default)]
+    decompress: Vec<ServiceConfig>,
+}
+
+#[cfg(feature = "config-file")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServiceConfig {
+    format: String,
+    #[serde(default)]
+    options: Vec<OptConfig>,
+    threads: Option<OptConfig>,
+    all_cores: Option<OptConfig>,
+    level: Option<OptConfig>,
+    #[serde(default)]
+    priority: usize,
+    min_version: Option<String>,
+    thread_min_version: Option<String>,
+    #[serde(default)]
+    requires: Vec<String>,
+}
+
+#[cfg(feature = "config-file")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OptConfig {
+    Args(String),
+    Env { env: String, value: String },
+}
+
+#[cfg(feature = "config-file")]
+impl From<OptConfig> for ToolOpt {
+    fn from(o: OptConfig) -> Self {
+        match o {
+            OptConfig::Args(s) => ToolOpt::args(s),
+            OptConfig::Env { env, value } => ToolOpt::env(env, value),
+        }
+    }
+}
+
+#[cfg(feature = "config-file")]
+fn parse_version(s: &str) -> io::Result<ToolVersion> {
+    s.parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Illegal version {}", s)))
+}
+
+#[cfg(feature = "config-file")]
+impl ServiceConfig {
+    fn into_service(self) -> io::Result<Service> {
+        let ct = match self.format.parse::<CompressType>() {
+            Ok(ct) => ct,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown compression format {}", self.format),
+                ))
+            }
+        };
+        let mut s = Service::new(ct).priority(self.priority);
+        for o in self.options {
+            s = s.option(o.into())
+        }
+        if let Some(o) = self.threads {
+            s = s.thread_option(o.into())
+        }
+        if let Some(o) = self.all_cores {
+            s = s.all_cores_option(o.into())
+        }
+        if let Some(o) = self.level {
+            s = s.level_option(o.into())
+        }
+        if let Some(v) = self.min_version {
+            s = s.min_version(parse_version(&v)?)
+        }
+        if let Some(v) = self.thread_min_version {
+            s = s.thread_min_version(parse_version(&v)?)
+        }
+        for c in self.requires {
+            s = s.requires(c)
+        }
+        Ok(s)
+    }
+}
+
+/// Read tool definitions from the configuration file `path` and apply them to `reg`.  The
+/// file is checked completely before any changes are made to `reg`.
+#[cfg(feature = "config-file")]
+pub fn apply_config_file<P: AsRef<Path>>(reg: &mut ToolRegister, path: P) -> io::Result<()> {
+    let s = fs::read_to_string(path)?;
+    let cfg: ConfigFile =
+        toml::from_str(&s).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    // Path only entries modify existing tools, otherwise a new tool is created
+    let mut programs = Vec::new();
+    let mut tools = Vec::new();
+    for tc in cfg.tool {
+        if tc.compress.is_empty() && tc.decompress.is_empty() {
+            match tc.path {
+                Some(p) => programs.push((tc.name, p)),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("No path or services given for tool {}", tc.name),
+                    ))
+                }
+            }
+        } else {
+            let mut tool = Tool::new(&tc.name);
+            if let Some(p) = tc.path {
+                tool = tool.program(p)
+            }
+            if let Some(a) = tc.version_args {
+                tool = tool.version_args(a)
+            }
+            if let Some(a) = tc.help_args {
+                tool = tool.help_args(a)
+            }
+            for s in tc.compress {
+                tool = tool.compress(s.into_service()?)
+            }
+            for s in tc.decompress {
+                tool = tool.decompress(s.into_service()?)
+            }
+            tool.validate()?;
+            tools.push(tool)
+        }
+    }
+    for (name, _) in programs.iter() {
+        if !reg.contains(name) && !tools.iter().any(|t| t.name() == name) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("No services given for unknown tool {}", name),
+            ));
+        }
+    }
+    for tool in tools {
+        reg.register(tool)?
+    }
+    for (name, p) in programs {
+        reg.set_program(&name, p)?
+    }
+    Ok(())
+}
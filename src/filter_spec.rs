@@ -4,6 +4,7 @@ use std::{
 };
 
 use super::compress_type::CompressType;
+use super::process::SpawnOptions;
 
 /// Representation of a filter: an external utility which will be spawned
 /// (using [`spawn`]) to filter an input or output stream.
@@ -21,7 +22,9 @@ use super::compress_type::CompressType;
 pub struct FilterSpec {
 	path: PathBuf,
 	args: Vec<Box<OsStr>>,
+	envs: Vec<(Box<OsStr>, Box<OsStr>)>,
 	compress_type: Option<CompressType>,
+	options: SpawnOptions,
 }
 
 impl FilterSpec {
@@ -32,7 +35,7 @@ impl FilterSpec {
 	{
 		let path = path.as_ref().to_owned();
 		let args: Vec<_> = args.into_iter().map(|s| Box::from(s.as_ref())).collect();
-		Self{path, args, compress_type: None}
+		Self{path, args, envs: Vec::new(), compress_type: None, options: SpawnOptions::default()}
 	}
 
 	pub(crate) fn new_compress<P: AsRef<Path>, I, S>(path: P, args: I, ctype: CompressType) -> Self
@@ -42,9 +45,32 @@ impl FilterSpec {
 	{
 		let path = path.as_ref().to_owned();
 		let args: Vec<_> = args.into_iter().map(|s| Box::from(s.as_ref())).collect();
-		Self{path, args, compress_type: Some(ctype)}
+		Self{path, args, envs: Vec::new(), compress_type: Some(ctype), options: SpawnOptions::default()}
 	}
 	
+	/// Set an environment variable for the spawned utility
+	pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, val: V) -> Self {
+		self.envs.push((Box::from(key.as_ref()), Box::from(val.as_ref())));
+		self
+	}
+
+	/// Set multiple environment variables for the spawned utility
+	pub fn envs<I, K, V>(mut self, vars: I) -> Self
+	where
+		I: IntoIterator<Item = (K, V)>,
+		K: AsRef<OsStr>,
+		V: AsRef<OsStr>,
+	{
+		self.envs.extend(vars.into_iter().map(|(k, v)| (Box::from(k.as_ref()), Box::from(v.as_ref()))));
+		self
+	}
+
+	/// Set the options used when spawning the utility (see [`process`](crate::process))
+	pub fn spawn_options(mut self, options: SpawnOptions) -> Self {
+		self.options = options;
+		self
+	}
+
 	pub(crate) fn cond_add_suffix<P: AsRef<Path>>(&self, name: P) -> PathBuf {
 		match self.compress_type {
 			Some(ct) => super::path_utils::cond_add_suffix(name, ct.suffix()),
@@ -55,5 +81,7 @@ impl FilterSpec {
 	pub fn compress_type(&self) -> Option<CompressType> { self.compress_type }
 	pub fn path(&self) -> &Path { &self.path}
 	pub fn args(&self) -> &[Box<OsStr>] { &self.args}
+	pub fn get_envs(&self) -> &[(Box<OsStr>, Box<OsStr>)] { &self.envs }
+	pub fn get_spawn_options(&self) -> &SpawnOptions { &self.options }
 }
 
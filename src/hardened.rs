@@ -0,0 +1,326 @@
+//! Hardened mode for use in security sensitive contexts
+//!
+//! By default compression utilities are found by searching the directories in `$PATH`, and
+//! inherit the environment of the calling process.  After [`enable`] is called:
+//!
+//! * Utilities are only found in the trusted directories given by [`Hardened`] (or at trusted
+//!   absolute paths), and `$PATH` is ignored.
+//! * Utilities in world-writable directories, or which are themselves world-writable, are
+//!   refused even if they are in a trusted directory.  Symbolic links are followed, so both the
+//!   link and its target must be in trusted locations and not world-writable.
+//! * Child processes are run with a minimal environment: `PATH` is set to the trusted
+//!   directories, and only the variables listed with [`Hardened::keep_env`] (by default `LANG`,
+//!   `LC_ALL` and `LC_CTYPE`) are passed from the environment of the calling process.  Utilities
+//!   that need any other environment variable set (i.e., by [`FilterSpec::env`]) are refused.
+//! * Configuration files other than `/etc/compress_io/tools.toml`, and the tool preferences
+//!   given by environment variables, are ignored (see [`config`](crate::config)).
+//! * All file descriptors other than `stdin`, `stdout` and `stderr` are closed in the child
+//!   processes.
+//!
+//! The checks are made when the tool register is set up (so untrusted utilities are treated as
+//! unavailable) and again each time a utility is spawned.  [`enable`] sets up the tool register
+//! again with the new settings, but readers and writers that have already been created are not
+//! affected, so it should be called at the start of the program.  Calling [`enable`] again
+//! replaces the settings; there is no way to leave hardened mode once it has been enabled.
+//!
+//! [`FilterSpec::env`]: crate::filter_spec::FilterSpec::env
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use compress_io::hardened::{self, Hardened};
+//!
+//! hardened::enable(Hardened::new().trusted_dirs(["/usr/bin", "/opt/zstd/bin"]));
+//! ```
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Error, ErrorKind},
+    os::unix::fs::PermissionsExt,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, RwLock},
+};
+
+static HARDENED: RwLock<Option<Arc<Hardened>>> = RwLock::new(None);
+
+const DEFAULT_TRUSTED: [&str; 3] = ["/usr/bin", "/bin", "/usr/local/bin"];
+const DEFAULT_KEEP_ENV: [&str; 3] = ["LANG", "LC_ALL", "LC_CTYPE"];
+
+/// Settings for hardened mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hardened {
+    trusted: Vec<PathBuf>,
+    keep_env: Vec<OsString>,
+}
+
+impl Default for Hardened {
+    fn default() -> Self {
+        Self {
+            trusted: DEFAULT_TRUSTED.iter().map(PathBuf::from).collect(),
+            keep_env: DEFAULT_KEEP_ENV.iter().map(OsString::from).collect(),
+        }
+    }
+}
+
+impl Hardened {
+    /// Creates settings with the default trusted directories (`/usr/bin`, `/bin` and
+    /// `/usr/local/bin`) and environment variables
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the trusted locations, replacing the defaults.  Each location can be a directory,
+    /// which will be searched for utilities in the order given, or the absolute path of a
+    /// utility.
+    pub fn trusted_dirs<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.trusted = paths.into_iter().map(|p| p.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Adds a trusted location (a directory or the absolute path of a utility)
+    pub fn trust<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.trusted.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Sets the environment variables passed to child processes (in addition to `PATH`),
+    /// replacing the defaults
+    pub fn keep_env<I, S>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.keep_env = vars.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    pub fn get_trusted(&self) -> &[PathBuf] {
+        &self.trusted
+    }
+
+    pub fn get_keep_env(&self) -> &[OsString] {
+        &self.keep_env
+    }
+
+    // Trusted directories (excluding paths to individual utilities)
+    fn dirs(&self) -> impl Iterator<Item = &Path> {
+        self.trusted
+            .iter()
+            .map(|p| p.as_path())
+            .filter(|p| p.is_absolute() && p.is_dir())
+    }
+
+    // Returns true if both `p` and (if `p` is a symbolic link) its target are in a trusted
+    // directory or are trusted paths.  Directories are canonicalized to allow for symbolic links
+    // (i.e., /bin -> /usr/bin)
+    fn is_trusted(&self, p: &Path) -> bool {
+        let link = p
+            .parent()
+            .and_then(|d| fs::canonicalize(d).ok())
+            .zip(p.file_name())
+            .map(|(d, name)| d.join(name));
+        match (link, fs::canonicalize(p).ok()) {
+            (Some(link), Some(target)) => self.trusts(&link) && self.trusts(&target),
+            _ => false,
+        }
+    }
+
+    // Returns true if `p` (which must have a canonical parent directory) is in a trusted
+    // directory or is a trusted path
+    fn trusts(&self, p: &Path) -> bool {
+        self.trusted
+            .iter()
+            .filter(|t| t.is_absolute())
+            .any(|t| match fs::canonicalize(t) {
+                Ok(c) if c.is_dir() => p.parent() == Some(c.as_path()),
+                Ok(c) => {
+                    // A trusted utility can be given either as a link or by its target
+                    let link = t
+                        .parent()
+                        .and_then(|d| fs::canonicalize(d).ok())
+                        .zip(t.file_name())
+                        .map(|(d, name)| d.join(name));
+                    p == c || link.as_deref() == Some(p)
+                }
+                Err(_) => false,
+            })
+    }
+
+    pub(crate) fn check_program(&self, p: &Path) -> io::Result<()> {
+        let refuse = |msg: &str| {
+            Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("Refusing to run {}: {}", p.display(), msg),
+            ))
+        };
+        if !p.is_absolute() {
+            return refuse("not an absolute path");
+        }
+        if !self.is_trusted(p) {
+            return refuse("not in a trusted location");
+        }
+        let target = fs::canonicalize(p)?;
+        for q in [p.parent(), Some(target.as_path()), target.parent()]
+            .into_iter()
+            .flatten()
+        {
+            if is_world_writable(q) {
+                return refuse(&format!("{} is world-writable", q.display()));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        cmd.env_clear();
+        if let Ok(path) = env::join_paths(self.dirs()) {
+            cmd.env("PATH", path);
+        }
+        for k in self.keep_env.iter() {
+            if let Some(v) = env::var_os(k) {
+                cmd.env(k, v);
+            }
+        }
+        unsafe { cmd.pre_exec(|| set_cloexec_from(3)) };
+    }
+
+    // Sets environment variables required by the utility on `cmd`, refusing any variable that
+    // is not listed in `keep_env` (as it could change how the utility is run, i.e., LD_PRELOAD)
+    pub(crate) fn set_envs<I, K, V>(&self, cmd: &mut Command, vars: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        let vars: Vec<_> = vars.into_iter().collect();
+        if let Some((k, _)) = vars
+            .iter()
+            .find(|(k, _)| !self.keep_env.iter().any(|e| e == k.as_ref()))
+        {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "Refusing to run {}: environment variable {} is not allowed",
+                    Path::new(cmd.get_program()).display(),
+                    k.as_ref().to_string_lossy()
+                ),
+            ));
+        }
+        cmd.envs(vars);
+        Ok(())
+    }
+}
+
+fn is_world_writable(p: &Path) -> bool {
+    fs::metadata(p)
+        .map(|m| m.permissions().mode() & 0o002 != 0)
+        .unwrap_or(true)
+}
+
+// Marks all file descriptors from `low` upwards as close-on-exec.  The descriptors are not closed
+// directly as the standard library uses a close-on-exec pipe to report errors from exec().
+fn set_cloexec_from(low: libc::c_int) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+        let r = unsafe {
+            libc::syscall(
+                libc::SYS_close_range,
+                low as libc::c_uint,
+                libc::c_uint::MAX,
+                CLOSE_RANGE_CLOEXEC,
+            )
+        };
+        if r == 0 {
+            return Ok(());
+        }
+    }
+    // Fallback for systems without close_range()
+    let mut lim: libc::rlimit = unsafe { std::mem::zeroed() };
+    let max = if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut lim) } == 0 {
+        (lim.rlim_cur as u64).min(65536) as libc::c_int
+    } else {
+        1024
+    };
+    for fd in low..max {
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            if flags >= 0 {
+                libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Enables hardened mode with the given settings, and searches for the tools again (see
+/// [`rescan`](crate::tools::rescan)) so that only trusted utilities are used.  Calling this again
+/// replaces the settings.
+pub fn enable(settings: Hardened) {
+    *HARDENED.write().unwrap() = Some(Arc::new(settings));
+    crate::tools::rescan()
+}
+
+/// Returns true if hardened mode is enabled
+pub fn is_enabled() -> bool {
+    HARDENED.read().unwrap().is_some()
+}
+
+/// Returns the current hardened mode settings, or `None` if hardened mode is not enabled
+pub fn settings() -> Option<Hardened> {
+    get().map(|h| (*h).clone())
+}
+
+fn get() -> Option<Arc<Hardened>> {
+    HARDENED.read().unwrap().clone()
+}
+
+/// Directories to search for utilities: the trusted directories in hardened mode, otherwise
+/// `None` (to use `$PATH`)
+pub(crate) fn search_dirs() -> Option<Vec<PathBuf>> {
+    get().map(|h| h.dirs().map(|p| p.to_owned()).collect())
+}
+
+/// Checks that `p` can be run.  Always succeeds unless hardened mode is enabled.
+pub(crate) fn check_program(p: &Path) -> io::Result<()> {
+    match get() {
+        Some(h) => h.check_program(p),
+        None => Ok(()),
+    }
+}
+
+/// Sets environment variables required by the utility on `cmd`.  In hardened mode only the
+/// variables listed with [`Hardened::keep_env`] can be set, and any other variable is refused.
+pub(crate) fn set_envs<I, K, V>(cmd: &mut Command, vars: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    match get() {
+        Some(h) => h.set_envs(cmd, vars),
+        None => {
+            cmd.envs(vars);
+            Ok(())
+        }
+    }
+}
+
+/// Checks the program to be run by `cmd` and applies the hardened environment and file
+/// descriptor settings (if hardened mode is enabled).  Must be called before any environment
+/// variables required by the utility are set on `cmd`.
+pub(crate) fn apply(cmd: &mut Command) -> io::Result<()> {
+    if let Some(h) = get() {
+        h.check_program(Path::new(cmd.get_program()))?;
+        h.apply(cmd)
+    }
+    Ok(())
+}
@@ -20,6 +20,8 @@
 //! can be overridden by [`ctype`].  `compress_io` will make use of parallel versions of
 //! compression utilities if available.  By default the compression utilities will be run using
 //! with the default threading options, but this behvaiour can be changed using [`cthreads`].
+//! The selection of utilities and the default threading options can also be adjusted by the
+//! user through environment variables (see [`config`]).
 //!
 //! ## Examples
 //!
@@ -100,6 +102,10 @@
 //! in the current `$PATH` then this will be used in favour [gzip].  For compression the user can
 //! specify a preference for threading (where available) using [`cthreads`].
 //!
+//! The utilities are located when first required.  [`tools::rescan`] will search for them again
+//! (for example, after `$PATH` has changed), and a different list of directories to search can be
+//! given for an individual reader or writer with [`search_path`].
+//!
 //! ```no_run
 //! # use std::io;
 //! use compress_io::{
@@ -116,6 +122,20 @@
 //! # }
 //! ```
 //!
+//! The same stream can be written to several outputs, each with its own compression settings,
+//! using a [`TeeWriter`], and a list of files, each in its own compression format, can be read as
+//! a single stream using a [`ConcatReader`].  Output can be split over several compressed files
+//! using a [`SplitWriter`].
+//!
+//! Compressed data can also be read from any [`Read`](std::io::Read) source, such as a network
+//! stream or an in-memory buffer, using [`reader_from`], and written to any
+//! [`Write`](std::io::Write) sink using [`writer_into`].
+//!
+//! Running compression utilities can be terminated (for example on `SIGINT`) with
+//! [`shutdown_all_children`], and can optionally be run in their own process groups and, on
+//! Linux, be killed if the thread creating them exits (see [`process`]).  For security sensitive contexts, a [`hardened`] mode restricts
+//! the locations from which utilities are run and the environment passed to them.
+//!
 //! ## Usage
 //!
 //! For usage with synchronous code only, add `compress_io` as a dependency in your `Cargo.toml` to
@@ -133,12 +153,33 @@
 //! compress_io = { version = "0.2", features = ["async"] }
 //! ```
 //!
+//! The `progress-bar` feature adds [`progress_bar`] to display an [indicatif] progress bar while
+//! reading or writing.
+//!
+//! The `log` feature emits [log] records describing the selection of compression utilities, the
+//! detection of compression formats, and the spawning and exit of child processes (with their
+//! arguments, process ids, exit statuses and run times).  Records are at the `debug` level,
+//! except for the detection of compression formats which is at the `trace` level.
+//!
 //! [`CompressIo`]: crate::compress::CompressIo
 //! [`AsyncCompressIo`]: crate::async::compress::AsyncCompressIo
 //! [`path`]: crate::compress::CompressIo::path
 //! [`ctype`]: crate::compress::CompressIo::ctype
 //! [`cthreads`]: crate::compress::CompressIo::cthreads
+//! [`tools::rescan`]: crate::tools::rescan
+//! [`search_path`]: crate::compress::CompressIo::search_path
+//! [`reader_from`]: crate::compress::CompressIo::reader_from
+//! [`writer_into`]: crate::compress::CompressIo::writer_into
 //! [`fix_path`]: crate::compress::CompressIo::fix_path
+//! [`config`]: crate::config
+//! [`progress_bar`]: crate::compress::CompressIo::progress_bar
+//! [indicatif]: https://docs.rs/indicatif
+//! [log]: https://docs.rs/log
+//! [`TeeWriter`]: crate::tee::TeeWriter
+//! [`ConcatReader`]: crate::concat::ConcatReader
+//! [`SplitWriter`]: crate::split::SplitWriter
+//! [`shutdown_all_children`]: crate::process::shutdown_all_children
+//! [`hardened`]: crate::hardened
 //!
 //! [`Read`]: std::io::Read
 //! [`BufRead`]: std::io::BufRead
@@ -155,11 +196,23 @@
 #[macro_use]
 extern crate lazy_static;
 
+#[macro_use]
+mod logging;
+
+pub mod cancel;
 pub mod compress;
 pub mod compress_type;
+pub mod concat;
+pub mod config;
 pub mod filter_spec;
+pub mod hardened;
 pub mod tools;
 pub mod path_utils;
+pub mod process;
+pub mod progress;
+pub mod split;
+pub mod stats;
+pub mod tee;
 
 #[cfg(feature = "async")]
 pub mod r#async;
@@ -167,12 +220,12 @@ pub mod r#async;
 #[cfg(test)]
 mod tests {
 	use crate::{
-		compress::CompressIo,
+		compress::{self, CompressIo},
 		compress_type::*
 	};
 	use std::{
 		path::{PathBuf, Path},
-		io::{Read, Write},
+		io::{BufRead, Read, Write},
 	};
 	use tempfile::TempDir;
 
@@ -305,4 +358,958 @@ mod tests {
 		let tdir = TmpDir::new();
 		tdir.test1_rw("test1", [CompressType::Zstd, CompressType::Zstd]);
 	}
+	#[test]
+	fn test_tool_opt() {
+		use crate::tools::*;
+		let s = Service::new(CompressType::Zstd)
+			.option(ToolOpt::args("-c -q"))
+			.option(ToolOpt::env("ZSTD_CLEVEL", "19"))
+			.thread_option(ToolOpt::args("-T {n}"))
+			.all_cores_option(ToolOpt::args("-T0"));
+		assert_eq!(s.args(CompressThreads::Default), ["-c", "-q"]);
+		assert_eq!(s.args(CompressThreads::Set(4)), ["-c", "-q", "-T", "4"]);
+		assert_eq!(s.args(CompressThreads::NCores), ["-c", "-q", "-T0"]);
+		assert_eq!(s.envs(CompressThreads::Set(4)), [("ZSTD_CLEVEL".to_string(), "19".to_string())]);
+		let s = Service::new(CompressType::Xz).thread_option(ToolOpt::env("XZ_OPT", "--threads={n}"));
+		assert_eq!(s.envs(CompressThreads::Set(2)), [("XZ_OPT".to_string(), "--threads=2".to_string())]);
+		assert!(s.args(CompressThreads::Set(2)).is_empty());
+		
+		let mut reg = ToolRegister::new();
+		assert!(reg.register(Tool::new("foo").compress(Service::new(CompressType::Gzip).thread_option(ToolOpt::args("-p")))).is_err());
+		assert!(reg.register(Tool::new("foo").compress(Service::new(CompressType::Gzip).option(ToolOpt::args("-p{n}")))).is_err());
+		assert!(reg.register(Tool::new("foo").compress(Service::new(CompressType::Gzip).option(ToolOpt::args("-p{m}")))).is_err());
+		assert!(reg.register(Tool::new("foo").compress(Service::new(CompressType::Gzip).option(ToolOpt::env("A=B", "1")))).is_err());
+		assert!(reg.register(Tool::new("foo").compress(Service::new(CompressType::Gzip).thread_option(ToolOpt::args("-p{n}")))).is_ok());
+	}
+	#[test]
+	fn test_tool_preference() {
+		use crate::tools::*;
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("gzip").compress(Service::new(CompressType::Gzip).priority(5))).unwrap();
+		reg.register(Tool::new("zstd").compress(Service::new(CompressType::Gzip).option(ToolOpt::args("--format=gzip")))).unwrap();
+		assert_eq!(reg.get_compress_tool(CompressType::Gzip).map(|t| t.name()), Some("gzip"));
+		reg.prefer(CompressType::Gzip, &["zstd", "gzip"]);
+		assert_eq!(reg.get_compress_tool(CompressType::Gzip).map(|t| t.name()), Some("zstd"));
+		reg.disable("zstd", Some(CompressType::Gzip));
+		assert_eq!(reg.get_compress_tool(CompressType::Gzip).map(|t| t.name()), Some("gzip"));
+		reg.disable("gzip", None);
+		assert!(reg.get_compress_tool(CompressType::Gzip).is_none());
+		
+		assert_eq!("BZIP2".parse::<CompressType>(), Ok(CompressType::Bzip2));
+		assert_eq!("ncores".parse::<CompressThreads>(), Ok(CompressThreads::NCores));
+		assert_eq!("8".parse::<CompressThreads>(), Ok(CompressThreads::Set(8)));
+		assert!("x".parse::<CompressThreads>().is_err());
+	}
+	#[cfg(feature = "config-file")]
+	#[test]
+	fn test_config_file() {
+		use crate::tools::*;
+		let tdir = TmpDir::new();
+		let cfg = tdir.mkpath("tools.toml");
+		let zstd = crate::path_utils::find_exec_path("zstd").expect("zstd not found");
+		std::fs::write(&cfg, format!(r#"
+[[tool]]
+name = "gzip"
+path = "/nonexistent/gzip"
+
+[[tool]]
+name = "my_zstd"
+path = "{}"
+
+[[tool.compress]]
+format = "gzip"
+options = ["--format=gzip", {{ env = "ZSTD_CLEVEL", value = "3" }}]
+threads = "-T{{n}}"
+priority = 20
+"#, zstd.display())).unwrap();
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("gzip").compress(Service::new(CompressType::Gzip).priority(5))).unwrap();
+		crate::config::apply_config_file(&mut reg, &cfg).expect("Error reading config file");
+		let tool = reg.get_compress_tool(CompressType::Gzip).expect("No tool found");
+		assert_eq!(tool.name(), "my_zstd");
+		assert_eq!(tool.path(), Some(zstd.as_path()));
+		let s = tool.get_compress(CompressType::Gzip).unwrap();
+		assert_eq!(s.args(CompressThreads::Set(2)), ["--format=gzip", "-T2"]);
+		reg.disable("my_zstd", None);
+		assert!(reg.get_compress_tool(CompressType::Gzip).is_none());
+		
+		std::fs::write(&cfg, "[[tool]]\nname = \"foo\"\n[[tool.compress]]\nformat = \"gzip\"\nthreads = \"-p\"\n").unwrap();
+		assert!(crate::config::apply_config_file(&mut reg, &cfg).is_err());
+		assert!(!reg.contains("foo"));
+	}
+	#[test]
+	fn test_tool_version() {
+		use crate::tools::*;
+		assert_eq!(ToolVersion::from_text("*** Zstandard CLI (64-bit) v1.5.7, by Yann Collet ***"), Some(ToolVersion::new(&[1, 5, 7])));
+		assert_eq!(ToolVersion::from_text("bzip2, a block-sorting file compressor.  Version 1.0.8, 13-Jul-2019."), Some(ToolVersion::new(&[1, 0, 8])));
+		assert!(ToolVersion::new(&[5, 2]) == ToolVersion::new(&[5, 2, 0]));
+		assert!(ToolVersion::new(&[5, 10]) > ToolVersion::new(&[5, 2, 5]));
+		
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("zstd")
+			.compress(Service::new(CompressType::Xz).requires("--no-such-option"))
+			.compress(Service::new(CompressType::Zstd).min_version(ToolVersion::new(&[1000])))
+			.compress(Service::new(CompressType::Gzip).min_version(ToolVersion::new(&[1])).thread_option(ToolOpt::args("-T{n}"))
+				.thread_min_version(ToolVersion::new(&[1000])))
+		).unwrap();
+		assert!(reg.get_compress_tool(CompressType::Xz).is_none());
+		assert!(reg.get_compress_tool(CompressType::Zstd).is_none());
+		let tool = reg.get_compress_tool(CompressType::Gzip).expect("zstd not found");
+		assert!(tool.version().is_some());
+		let s = tool.get_compress(CompressType::Gzip).unwrap();
+		assert_eq!(tool.threads(s, CompressThreads::Set(4)), CompressThreads::Default);
+	}
+	#[test]
+	fn test_tool_probe() {
+		use crate::tools::*;
+		use std::{fs, os::unix::fs::PermissionsExt, time::{Duration, Instant}};
+		
+		let tdir = TmpDir::new();
+		let script = |name: &str, body: &str| {
+			let p = tdir.mkpath(name);
+			fs::write(&p, format!("#!/bin/sh\n{}\n", body)).unwrap();
+			fs::set_permissions(&p, fs::Permissions::from_mode(0o755)).unwrap();
+			p
+		};
+		
+		// Options must be listed as whole words at the start of a line
+		let help = script("helper", "echo '  -T#, --threads=#   use # threads'; echo '  --fast    see also --format=xz'");
+		let mut tool = Tool::new("helper").program(&help);
+		tool.add_path();
+		assert!(tool.has_capability("--threads"));
+		assert!(tool.has_capability("-T#"));
+		assert!(tool.has_capability("--fast"));
+		assert!(!tool.has_capability("--format=xz"));
+		assert!(!tool.has_capability("--thread"));
+		
+		// A tool that does not finish is killed and treated as lacking the capability
+		let hang = script("hang", "exec sleep 60");
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("hang").program(&hang).probe_timeout(Duration::from_millis(200))
+			.compress(Service::new(CompressType::Gzip).requires("--fast"))).unwrap();
+		let start = Instant::now();
+		assert!(reg.get_compress_tool(CompressType::Gzip).is_none());
+		assert!(start.elapsed() < Duration::from_secs(5));
+	}
+	#[test]
+	fn test_self_test() {
+		use crate::tools::{ServiceMode, TestOutcome};
+		let report = crate::tools::self_test();
+		assert!(report.passed(), "{}", report);
+		let gzip = |mode| report.tests().iter().any(|t| t.tool == "gzip" && t.mode == mode
+			&& t.compress_type == CompressType::Gzip && t.outcome == TestOutcome::Passed);
+		assert!(gzip(ServiceMode::Compress) && gzip(ServiceMode::Decompress), "{}", report);
+	}
+	#[test]
+	fn test_registry_info() {
+		use crate::tools::*;
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("no_such_tool").compress(Service::new(CompressType::Gzip).priority(20))).unwrap();
+		reg.register(Tool::new("gzip").compress(Service::new(CompressType::Gzip).priority(5))).unwrap();
+		reg.register(Tool::new("zstd").compress(Service::new(CompressType::Gzip).option(ToolOpt::args("--format=gzip")))).unwrap();
+		let info = reg.info();
+		let gz = info.format(CompressType::Gzip).unwrap();
+		assert_eq!(gz.selected_compress(), Some("gzip"));
+		let status: Vec<_> = gz.compress.iter().map(|c| (c.tool.as_str(), c.status)).collect();
+		assert_eq!(status, [("no_such_tool", CandidateStatus::NotFound), ("gzip", CandidateStatus::Selected), ("zstd", CandidateStatus::Available)]);
+		assert!(gz.selected_decompress().is_none());
+		assert!(info.tools[1].version.is_some());
+		assert!(info.to_string().contains("candidates: no_such_tool(20, not found) gzip(5, selected) zstd(0, available)"));
+	}
+	#[test]
+	fn test_finish() {
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(1000);
+		let mut wrt = CompressIo::new().path(&name).level(9).writer().expect("Could not make file");
+		write!(wrt, "{}", test_string).expect("Error writing to file");
+		wrt.finish().expect("Error finishing writer");
+		
+		let mut rdr = CompressIo::new().path(&name).reader().expect("Couldn't open reader");
+		let mut buf = String::new();
+		rdr.read_to_string(&mut buf).expect("Couldn't read from file");
+		assert_eq!(buf, test_string);
+		rdr.finish().expect("Error finishing reader");
+		
+		// Truncated input should be reported as an error by finish()
+		let data = std::fs::read(&name).unwrap();
+		let trunc = tdir.mkpath("trunc.gz");
+		std::fs::write(&trunc, &data[..data.len() / 2]).unwrap();
+		let rdr = CompressIo::new().path(&trunc).reader().expect("Couldn't open reader");
+		assert!(rdr.finish().is_err());
+	}
+	
+	#[test]
+	fn test_transcode() {
+		let tdir = TmpDir::new();
+		let gz = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(1000);
+		let mut wrt = CompressIo::new().path(&gz).writer().expect("Could not make file");
+		write!(wrt, "{}", test_string).expect("Error writing to file");
+		wrt.finish().expect("Error finishing writer");
+		
+		// Compressed to compressed
+		let bz = tdir.mkpath("test.bz2");
+		let status = compress::transcode(CompressIo::new().path(&gz), CompressIo::new().path(&bz)).expect("Transcode failed");
+		assert!(status.decompress.is_some() && status.compress.is_some());
+		status.check().expect("Transcode child failed");
+		assert!(std::fs::read(&bz).unwrap().starts_with(b"BZh"));
+		
+		// Compressed to uncompressed and back
+		let txt = tdir.mkpath("test.txt");
+		let status = compress::transcode(CompressIo::new().path(&bz), CompressIo::new().path(&txt)).expect("Transcode failed");
+		assert!(status.success() && status.compress.is_none());
+		assert_eq!(std::fs::read_to_string(&txt).unwrap(), test_string);
+		let xz = tdir.mkpath("test.xz");
+		let status = compress::transcode(CompressIo::new().path(&txt), CompressIo::new().path(&xz)).expect("Transcode failed");
+		assert!(status.success() && status.decompress.is_none());
+		
+		let mut buf = String::new();
+		CompressIo::new().path(&xz).reader().unwrap().read_to_string(&mut buf).unwrap();
+		assert_eq!(buf, test_string);
+	}
+	
+	#[test]
+	fn test_copy() {
+		let tdir = TmpDir::new();
+		let txt = tdir.mkpath("test.txt");
+		let test_string = "Testing testing 123\n".repeat(10000);
+		std::fs::write(&txt, &test_string).unwrap();
+		let len = test_string.len() as u64;
+		
+		// File to file
+		let txt1 = tdir.mkpath("test1.txt");
+		let mut rdr = CompressIo::new().path(&txt).reader().unwrap();
+		let mut wrt = CompressIo::new().path(&txt1).writer().unwrap();
+		assert_eq!(compress::copy(&mut rdr, &mut wrt).expect("Copy failed"), len);
+		wrt.finish().unwrap();
+		assert_eq!(std::fs::read_to_string(&txt1).unwrap(), test_string);
+		
+		// File to compressor
+		let gz = tdir.mkpath("test.gz");
+		let mut rdr = CompressIo::new().path(&txt).reader().unwrap();
+		let mut wrt = CompressIo::new().path(&gz).writer().unwrap();
+		assert_eq!(compress::copy(&mut rdr, &mut wrt).expect("Copy failed"), len);
+		wrt.finish().expect("Error finishing writer");
+		
+		// Decompressor to file
+		let txt2 = tdir.mkpath("test2.txt");
+		let mut rdr = CompressIo::new().path(&gz).reader().unwrap();
+		let mut wrt = CompressIo::new().path(&txt2).writer().unwrap();
+		assert_eq!(compress::copy(&mut rdr, &mut wrt).expect("Copy failed"), len);
+		rdr.finish().expect("Error finishing reader");
+		wrt.finish().unwrap();
+		assert_eq!(std::fs::read_to_string(&txt2).unwrap(), test_string);
+	}
+	
+	#[test]
+	fn test_tee() {
+		let tdir = TmpDir::new();
+		let test_string = "Testing testing 123\n".repeat(1000);
+		let cfgs: Vec<_> = ["test.gz", "test.zst", "test.txt"].iter()
+			.map(|s| CompressIo::new().path(tdir.mkpath(s)).clone()).collect();
+		let mut wrt = crate::tee::TeeWriter::new(&cfgs).expect("Could not make tee writer");
+		write!(wrt, "{}", test_string).expect("Error writing to tee");
+		let report = wrt.finish();
+		assert!(report.success());
+		for res in report.outputs() {
+			let mut rdr = CompressIo::new().path(res.path.as_ref().unwrap()).reader().unwrap();
+			let mut buf = String::new();
+			rdr.read_to_string(&mut buf).unwrap();
+			assert_eq!(buf, test_string);
+		}
+		
+		// Compression type given without suffix adds the suffix
+		let mut cio = CompressIo::new();
+		cio.path(tdir.mkpath("foo")).ctype(CompressType::Bzip2);
+		let report = crate::tee::TeeWriter::new([&cio]).unwrap().finish();
+		assert_eq!(report.outputs()[0].path.as_deref(), Some(tdir.mkpath("foo.bz2").as_path()));
+		report.check().unwrap();
+		assert!(crate::tee::TeeWriter::new(&[]).is_err());
+		
+		// If an output can not be opened then the outputs already created are removed
+		let cfgs: Vec<_> = ["first.gz", "missing/second.gz"].iter()
+			.map(|s| CompressIo::new().path(tdir.mkpath(s)).clone()).collect();
+		assert!(crate::tee::TeeWriter::new(&cfgs).is_err());
+		assert!(!tdir.mkpath("first.gz").exists());
+	}
+	
+	#[test]
+	fn test_concat() {
+		let tdir = TmpDir::new();
+		let names = ["a.gz", "b.zst", "c.txt", "d.txt", "e.bz2"];
+		let contents = ["gzip line 1\ngzip line 2\n", "zstd line\n", "plain line\n", "", "bzip2 line\n"];
+		let paths: Vec<_> = names.iter().map(|s| tdir.mkpath(s)).collect();
+		for (p, s) in paths.iter().zip(contents.iter()) {
+			let mut wrt = CompressIo::new().path(p).writer().unwrap();
+			write!(wrt, "{}", s).unwrap();
+			wrt.finish().unwrap();
+		}
+		
+		let mut rdr = crate::concat::ConcatReader::new(&paths);
+		let mut line = String::new();
+		rdr.read_line(&mut line).unwrap();
+		assert_eq!(line, "gzip line 1\n");
+		assert_eq!(rdr.path(), Some(paths[0].as_path()));
+		assert_eq!(rdr.offset(), 12);
+		let mut buf = String::new();
+		rdr.read_to_string(&mut buf).unwrap();
+		assert_eq!(line + &buf, contents.concat());
+		assert_eq!(rdr.total_offset(), contents.concat().len() as u64);
+		assert!(rdr.path().is_none());
+		
+		// Truncated file in the middle of the list
+		let data = std::fs::read(&paths[0]).unwrap();
+		std::fs::write(&paths[1], &data[..data.len() / 2]).unwrap();
+		let mut rdr = crate::concat::ConcatReader::new(&paths);
+		let mut buf = String::new();
+		assert!(rdr.read_to_string(&mut buf).is_err());
+		assert_eq!(rdr.file_index(), 1);
+		assert!(rdr.position().to_string().contains("b.zst (file 2 of 5)"));
+	}
+	
+	#[test]
+	fn test_split() {
+		use crate::split::{SplitLimit, SplitWriter};
+		let tdir = TmpDir::new();
+		let test_string: String = (0..10).map(|i| format!("Line {:04}\n", i)).collect();
+		
+		let mut wrt = SplitWriter::new(CompressIo::new().path(tdir.mkpath("part.gz")), SplitLimit::Records(3)).unwrap();
+		write!(wrt, "{}", test_string).unwrap();
+		let paths = wrt.finish().expect("Error finishing split writer");
+		assert_eq!(paths.len(), 4);
+		assert_eq!(paths[0], tdir.mkpath("part-0000.gz"));
+		assert_eq!(paths[3], tdir.mkpath("part-0003.gz"));
+		let mut buf = String::new();
+		CompressIo::new().path(&paths[1]).reader().unwrap().read_to_string(&mut buf).unwrap();
+		assert_eq!(buf, "Line 0003\nLine 0004\nLine 0005\n");
+		
+		// Byte limits only split at the end of a line, and suffix is added after the part number
+		let mut wrt = SplitWriter::new(CompressIo::new().path(tdir.mkpath("part")).ctype(CompressType::Zstd), SplitLimit::Bytes(25)).unwrap();
+		for c in test_string.as_bytes().chunks(7) {
+			wrt.write_all(c).unwrap();
+		}
+		let paths = wrt.finish().expect("Error finishing split writer");
+		assert_eq!(paths.len(), 4);
+		assert_eq!(paths[0], tdir.mkpath("part-0000.zst"));
+		let mut rdr = crate::concat::ConcatReader::new(&paths);
+		let mut buf = String::new();
+		rdr.read_to_string(&mut buf).unwrap();
+		assert_eq!(buf, test_string);
+		
+		assert!(SplitWriter::new(&CompressIo::new(), SplitLimit::Records(3)).is_err());
+		
+		// If a new part can not be started, the data already written is reported and the error
+		// is returned by the next call
+		std::fs::create_dir(tdir.mkpath("fail-0001.txt")).unwrap();
+		let mut wrt = SplitWriter::new(CompressIo::new().path(tdir.mkpath("fail.txt")), SplitLimit::Records(1)).unwrap();
+		assert_eq!(wrt.write(b"a\nb\n").unwrap(), 2);
+		assert!(wrt.write(b"b\n").is_err());
+		assert_eq!(std::fs::read_to_string(tdir.mkpath("fail-0000.txt")).unwrap(), "a\n");
+	}
+	
+	#[test]
+	fn test_stats() {
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(10000);
+		let len = test_string.len() as u64;
+		let mut wrt = CompressIo::new().path(&name).writer().expect("Could not make file");
+		write!(wrt, "{}", test_string).expect("Error writing to file");
+		assert_eq!(wrt.stats().bytes, len);
+		let stats = wrt.finish().expect("Error finishing writer");
+		let size = std::fs::metadata(&name).unwrap().len();
+		assert_eq!(stats.bytes, len);
+		assert_eq!(stats.compressed_bytes, Some(size));
+		assert!(stats.ratio().unwrap() < 1.0);
+		assert!(stats.child_cpu.is_some());
+		
+		let mut rdr = CompressIo::new().path(&name).reader().expect("Couldn't open reader");
+		let mut buf = [0; 100];
+		rdr.read_exact(&mut buf).unwrap();
+		assert_eq!(rdr.stats().bytes, 100);
+		assert!(rdr.stats().child_cpu.is_none());
+		let stats = rdr.finish().expect("Error finishing reader");
+		assert_eq!(stats.bytes, len);
+		assert_eq!(stats.compressed_bytes, Some(size));
+		assert!(stats.child_cpu.is_some());
+		
+		// Uncompressed files
+		let txt = tdir.mkpath("test.txt");
+		std::fs::write(&txt, &test_string).unwrap();
+		let mut rdr = CompressIo::new().path(&txt).reader().unwrap();
+		rdr.read_exact(&mut buf).unwrap();
+		let stats = rdr.finish().unwrap();
+		assert_eq!((stats.bytes, stats.compressed_bytes, stats.child_cpu), (100, Some(100), None));
+	}
+	
+	#[test]
+	fn test_progress() {
+		use std::sync::{Arc, Mutex};
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(10000);
+		let reports = Arc::new(Mutex::new(Vec::new()));
+		let r = reports.clone();
+		let mut cio = CompressIo::new();
+		cio.path(&name)
+			.progress(move |p| r.lock().unwrap().push(*p))
+			.progress_interval(std::time::Duration::ZERO);
+		let mut wrt = cio.writer().expect("Could not make file");
+		wrt.write_all(test_string.as_bytes()).unwrap();
+		wrt.finish().unwrap();
+		{
+			let rep = reports.lock().unwrap();
+			let last = rep.last().expect("No progress reports");
+			assert!(last.finished && last.compressed_total.is_none());
+			assert_eq!(last.bytes, test_string.len() as u64);
+		}
+		
+		reports.lock().unwrap().clear();
+		let mut rdr = cio.reader().expect("Couldn't open reader");
+		let mut buf = [0; 4096];
+		rdr.read_exact(&mut buf).unwrap();
+		rdr.finish().unwrap();
+		let rep = reports.lock().unwrap();
+		assert!(rep.len() >= 2);
+		assert!(rep.iter().all(|p| p.compressed_total == Some(std::fs::metadata(&name).unwrap().len())));
+		assert!(rep[0].bytes > 0 && !rep[0].finished);
+		assert_eq!(rep.last().unwrap().fraction(), Some(1.0));
+	}
+	
+	#[cfg(feature = "progress-bar")]
+	#[test]
+	fn test_progress_bar() {
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.zst");
+		let test_string = "Testing testing 123\n".repeat(10000);
+		let mut cio = CompressIo::new();
+		cio.path(&name).progress_bar();
+		let mut wrt = cio.writer().expect("Could not make file");
+		wrt.write_all(test_string.as_bytes()).unwrap();
+		wrt.finish().unwrap();
+		
+		let mut rdr = cio.reader().expect("Couldn't open reader");
+		let mut buf = String::new();
+		rdr.read_to_string(&mut buf).unwrap();
+		assert_eq!(buf, test_string);
+		rdr.finish().unwrap();
+		
+		let bar = indicatif::ProgressBar::hidden();
+		bar.set_length(100);
+		let cb = crate::progress::bar_callback(bar.clone());
+		cb(&crate::progress::Progress { bytes: 200, compressed_bytes: Some(50), compressed_total: Some(100), ..Default::default() });
+		assert_eq!(bar.position(), 50);
+	}
+	
+	#[test]
+	fn test_timeouts() {
+		use crate::cancel::{self, CancelHandle, Timeouts};
+		use std::{process::{Command, Stdio}, time::{Duration, Instant}};
+		
+		let sleeper = || {
+			let child = Command::new("sleep").arg("10").stdout(Stdio::piped()).spawn().expect("Couldn't run sleep");
+			compress::Reader::from_child(child)
+		};
+		let mut buf = [0; 16];
+		
+		// Inactivity timeout
+		let start = Instant::now();
+		let mut rdr = sleeper();
+		rdr.set_timeouts(&Timeouts { inactivity: Some(Duration::from_millis(100)), ..Default::default() });
+		let e = rdr.read(&mut buf).unwrap_err();
+		assert_eq!(e.kind(), std::io::ErrorKind::TimedOut);
+		assert!(rdr.finish().is_err());
+		assert!(start.elapsed() < Duration::from_secs(5));
+		
+		// Cancellation from another thread
+		let start = Instant::now();
+		let handle = CancelHandle::new();
+		let mut rdr = sleeper();
+		rdr.set_timeouts(&Timeouts { cancel: Some(handle.clone()), ..Default::default() });
+		let h = handle.clone();
+		std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(100));
+			h.cancel()
+		});
+		let e = rdr.read(&mut buf).unwrap_err();
+		assert!(cancel::is_cancelled(&e));
+		assert!(start.elapsed() < Duration::from_secs(5));
+		
+		// Normal operation is unaffected
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(10000);
+		let mut cio = CompressIo::new();
+		cio.path(&name).inactivity_timeout(Duration::from_secs(30)).timeout(Duration::from_secs(60)).cancel_handle(&CancelHandle::new());
+		let mut wrt = cio.writer().unwrap();
+		wrt.write_all(test_string.as_bytes()).unwrap();
+		wrt.finish().expect("Error finishing writer");
+		let mut rdr = cio.reader().unwrap();
+		let mut s = String::new();
+		rdr.read_to_string(&mut s).unwrap();
+		assert_eq!(s, test_string);
+		rdr.finish().expect("Error finishing reader");
+		
+		// Readers from a cancelled handle fail
+		cio.cancel_handle(&handle);
+		assert!(cio.reader().unwrap().read(&mut buf).is_err());
+	}
+
+	#[test]
+	fn test_process_group() {
+		let tdir = TmpDir::new();
+		let own_group = unsafe { libc::getpgid(0) };
+		for (grouped, fname) in [(true, "test1.gz"), (false, "test2.gz")] {
+			let name = tdir.mkpath(fname);
+			let mut wrt = CompressIo::new().path(&name).process_group(grouped).kill_on_parent_death(grouped).writer().unwrap();
+			let mut child = wrt.take_child().unwrap();
+			let pgid = unsafe { libc::getpgid(child.id() as libc::pid_t) };
+			if grouped {
+				assert_eq!(pgid, child.id() as libc::pid_t);
+			} else {
+				assert_eq!(pgid, own_group);
+			}
+			writeln!(wrt, "Testing testing 123").unwrap();
+			drop(wrt);
+			assert!(child.wait().unwrap().success());
+			let mut s = String::new();
+			CompressIo::new().path(&name).reader().unwrap().read_to_string(&mut s).unwrap();
+			assert_eq!(s, "Testing testing 123\n");
+		}
+	}
+
+	#[test]
+	fn test_spawn_defaults() {
+		use std::time::Duration;
+		
+		let opts = crate::process::SpawnOptions::new();
+		assert!(!opts.get_kill_on_parent_death() && !opts.get_process_group());
+		
+		// A no_wait writer is not killed when the thread creating it exits
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let cio = CompressIo::new().path(&name).no_wait().kill_on_parent_death(true).clone();
+		let mut wrt = std::thread::spawn(move || cio.writer().unwrap()).join().unwrap();
+		let completion = wrt.completion().unwrap();
+		writeln!(wrt, "Testing testing 123").unwrap();
+		wrt.finish().unwrap();
+		assert!(completion.wait_timeout(Duration::from_secs(30)).unwrap().expect("Child did not finish").success());
+		let mut s = String::new();
+		CompressIo::new().path(&name).reader().unwrap().read_to_string(&mut s).unwrap();
+		assert_eq!(s, "Testing testing 123\n");
+	}
+	#[test]
+	fn test_no_wait() {
+		use std::time::Duration;
+		
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let test_string = "Testing testing 123\n".repeat(1000);
+		let mut wrt = CompressIo::new().path(&name).no_wait().writer().unwrap();
+		let completion = wrt.completion().unwrap();
+		assert!(crate::process::background_children().iter().any(|c| c.pid() == completion.pid()));
+		wrt.write_all(test_string.as_bytes()).unwrap();
+		assert_eq!(completion.try_wait().unwrap(), None);
+		wrt.finish().unwrap();
+		let status = completion.wait_timeout(Duration::from_secs(30)).unwrap().expect("Child did not finish");
+		assert!(status.success());
+		assert!(completion.is_finished());
+		assert!(!crate::process::background_children().iter().any(|c| c.pid() == completion.pid()));
+		let mut s = String::new();
+		CompressIo::new().path(&name).reader().unwrap().read_to_string(&mut s).unwrap();
+		assert_eq!(s, test_string);
+		
+		// Child process has been reaped, so is no longer a zombie
+		assert!(!std::path::Path::new(&format!("/proc/{}", completion.pid())).exists());
+		assert!(CompressIo::new().path(&name).writer().unwrap().completion().is_none());
+	}
+
+	#[test]
+	fn test_stderr() {
+		use crate::process::StderrPolicy;
+		use std::sync::{Arc, Mutex};
+		
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("corrupt.gz");
+		std::fs::write(&name, b"\x1f\x8b\x08\x00 not really gzip data").unwrap();
+		let read_all = |cio: &CompressIo| {
+			let mut rdr = cio.reader().unwrap();
+			let mut v = Vec::new();
+			let _ = rdr.read_to_end(&mut v);
+			rdr.finish()
+		};
+		
+		// Captured messages are added to the error
+		let e = read_all(CompressIo::new().path(&name).stderr(StderrPolicy::Capture(4096))).unwrap_err();
+		assert!(e.to_string().contains("gzip"), "{}", e);
+		
+		// Discarded messages are not
+		let e = read_all(CompressIo::new().path(&name).stderr(StderrPolicy::Discard)).unwrap_err();
+		assert!(!e.to_string().contains("gzip"), "{}", e);
+		
+		// Forwarded messages are passed to the callback by line
+		let lines = Arc::new(Mutex::new(Vec::new()));
+		let l = lines.clone();
+		let policy = StderrPolicy::forward(move |s| l.lock().unwrap().push(s.to_owned()));
+		assert!(read_all(CompressIo::new().path(&name).stderr(policy)).is_err());
+		let lines = lines.lock().unwrap();
+		assert!(!lines.is_empty());
+		assert!(lines.iter().all(|s| !s.ends_with('\n')));
+		
+		// Only the last bytes are kept
+		let e = read_all(CompressIo::new().path(&name).stderr(StderrPolicy::Capture(8))).unwrap_err();
+		let msg = e.to_string();
+		let extra = msg.rsplit_once(": ").map(|(_, x)| x.len()).unwrap();
+		assert!(extra <= 8, "{}", msg);
+		
+		// Transcoding reports captured messages
+		let status = compress::transcode(CompressIo::new().path(&name).stderr(StderrPolicy::Capture(4096)), CompressIo::new().path(tdir.mkpath("out.xz"))).unwrap();
+		assert!(status.decompress_stderr.is_some());
+		assert!(status.check().unwrap_err().to_string().contains("gzip"));
+	}
+
+	#[cfg(feature = "log")]
+	#[test]
+	fn test_log() {
+		use std::sync::Mutex;
+		
+		struct TestLogger(Mutex<Vec<String>>);
+		impl log::Log for TestLogger {
+			fn enabled(&self, _: &log::Metadata) -> bool { true }
+			fn log(&self, record: &log::Record) { self.0.lock().unwrap().push(record.args().to_string()) }
+			fn flush(&self) {}
+		}
+		static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+		log::set_logger(&LOGGER).unwrap();
+		log::set_max_level(log::LevelFilter::Trace);
+		
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let mut wrt = CompressIo::new().path(&name).writer().unwrap();
+		writeln!(wrt, "Testing testing 123").unwrap();
+		wrt.finish().unwrap();
+		let mut s = String::new();
+		let mut rdr = CompressIo::new().path(&name).reader().unwrap();
+		rdr.read_to_string(&mut s).unwrap();
+		rdr.finish().unwrap();
+		
+		let records = LOGGER.0.lock().unwrap();
+		for msg in ["Selected gzip", "Spawned", "Detected compression type gzip", "exited (exit status: 0)"] {
+			assert!(records.iter().any(|r| r.contains(msg)), "No record containing '{}' in {:?}", msg, records)
+		}
+	}
+
+	#[test]
+	fn test_resources() {
+		use crate::process::{IoPriority, Resources};
+		
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let res = Resources::new().nice(10).io_priority(IoPriority::Idle).cpu_affinity([0]).memory_limit(1 << 32).cpu_time_limit(60);
+		let mut wrt = CompressIo::new().path(&name).resources(res).writer().unwrap();
+		let mut child = wrt.take_child().unwrap();
+		let proc_file = |f: &str| std::fs::read_to_string(format!("/proc/{}/{}", child.id(), f)).unwrap();
+		
+		// The nice level is field 19 of /proc/<pid>/stat (after the command name in parentheses)
+		let stat = proc_file("stat");
+		let fields: Vec<_> = stat.rsplit_once(')').unwrap().1.split_whitespace().collect();
+		assert_eq!(fields[16], "10");
+		assert!(proc_file("status").lines().any(|l| l.starts_with("Cpus_allowed_list:") && l.ends_with("\t0")));
+		let limits = proc_file("limits");
+		assert!(limits.lines().any(|l| l.starts_with("Max cpu time") && l.contains(" 60 ")));
+		assert!(limits.lines().any(|l| l.starts_with("Max address space") && l.contains(" 4294967296 ")));
+		
+		writeln!(wrt, "Testing testing 123").unwrap();
+		drop(wrt);
+		assert!(child.wait().unwrap().success());
+		let mut s = String::new();
+		CompressIo::new().path(&name).reader().unwrap().read_to_string(&mut s).unwrap();
+		assert_eq!(s, "Testing testing 123\n");
+		
+		// Settings that can not be applied cause an error
+		let res = Resources::new().nice(-20);
+		if unsafe { libc::geteuid() } != 0 {
+			assert!(CompressIo::new().path(tdir.mkpath("test2.gz")).resources(res).writer().is_err());
+		}
+	}
+
+	#[test]
+	fn test_hardened() {
+		use crate::hardened::Hardened;
+		use std::{fs, os::unix::{fs::PermissionsExt, io::AsRawFd}, process::Command};
+		
+		let tdir = TmpDir::new();
+		let bin = tdir.mkpath("bin");
+		fs::create_dir(&bin).unwrap();
+		fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+		let tool = bin.join("tool");
+		fs::write(&tool, "#!/bin/sh\necho $FOO $LANG $PATH\nls /proc/$$/fd\n").unwrap();
+		fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+		let h = Hardened::new().trusted_dirs([&bin]).keep_env(["LANG"]);
+		
+		// Only trusted locations are accepted
+		assert!(h.check_program(&tool).is_ok());
+		assert!(h.check_program(std::path::Path::new("tool")).is_err());
+		assert!(Hardened::new().trusted_dirs([tdir.mkpath("other")]).check_program(&tool).is_err());
+		assert!(Hardened::new().trusted_dirs([&tool]).check_program(&tool).is_ok());
+		
+		// Symbolic links must point to trusted locations
+		let other = tdir.mkpath("other");
+		fs::create_dir(&other).unwrap();
+		fs::set_permissions(&other, fs::Permissions::from_mode(0o755)).unwrap();
+		fs::copy(&tool, other.join("tool")).unwrap();
+		std::os::unix::fs::symlink(other.join("tool"), bin.join("link")).unwrap();
+		assert!(h.check_program(&bin.join("link")).is_err());
+		assert!(h.clone().trust(&other).check_program(&bin.join("link")).is_ok());
+		std::os::unix::fs::symlink(&tool, other.join("link")).unwrap();
+		assert!(h.check_program(&other.join("link")).is_err());
+		
+		// The child gets a minimal environment and no extra file descriptors.  A descriptor
+		// without close-on-exec is created with dup()
+		let file = fs::File::create(tdir.mkpath("leak")).unwrap();
+		let fd = unsafe { libc::dup(file.as_raw_fd()) };
+		assert!(fd > 2);
+		let mut cmd = Command::new(&tool);
+		cmd.env("FOO", "foo").env("LANG", "C");
+		h.apply(&mut cmd);
+		let output = cmd.output().unwrap();
+		unsafe { libc::close(fd) };
+		let out = String::from_utf8(output.stdout).unwrap();
+		let mut lines = out.lines();
+		assert_eq!(lines.next(), Some(format!("{} {}", std::env::var("LANG").unwrap_or_default(), bin.display()).trim()));
+		assert!(lines.all(|l| l != fd.to_string()), "{}", out);
+		
+		// World-writable locations are refused
+		fs::set_permissions(&bin, fs::Permissions::from_mode(0o777)).unwrap();
+		assert!(h.check_program(&tool).is_err());
+		fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+		fs::set_permissions(&tool, fs::Permissions::from_mode(0o777)).unwrap();
+		assert!(h.check_program(&tool).is_err());
+	}
+	#[test]
+	fn test_hardened_env() {
+		use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+		
+		// Hardened mode is global, so is enabled in a separate run of the test binary
+		let tdir = TmpDir::new();
+		let bin = tdir.mkpath("bin");
+		fs::create_dir(&bin).unwrap();
+		fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+		let tool = bin.join("tool");
+		fs::write(&tool, "#!/bin/sh\necho $FOO $LANG\n").unwrap();
+		fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+		let cfg = tdir.mkpath("tools.toml");
+		fs::write(&cfg, format!("[[tool]]\nname = \"evil\"\npath = \"{}\"\n\n[[tool.decompress]]\nformat = \"gzip\"\noptions = [{{ env = \"FOO\", value = \"injected\" }}]\npriority = 100\n", tool.display())).unwrap();
+		let all: Vec<_> = crate::tools::registry().tools().iter().map(|t| t.name().to_owned()).collect();
+		let status = Command::new(std::env::current_exe().unwrap())
+			.args(["--exact", "tests::hardened_env_child", "--ignored", "--test-threads=1"])
+			.env("COMPRESS_IO_TEST_BIN", &bin)
+			.env(crate::config::ENV_CONFIG, &cfg)
+			.env(crate::config::ENV_DISABLE, all.join(","))
+			.status().unwrap();
+		assert!(status.success());
+	}
+	#[test]
+	#[ignore]
+	fn hardened_env_child() {
+		use crate::{filter_spec::FilterSpec, hardened::{self, Hardened}};
+		use std::{io::ErrorKind, process::Stdio};
+		
+		// Run by test_hardened_env()
+		let Some(bin) = std::env::var_os("COMPRESS_IO_TEST_BIN").map(PathBuf::from) else { return };
+		// The register is set up again when hardened mode is enabled
+		#[cfg(feature = "config-file")]
+		assert!(crate::tools::registry().contains("evil"));
+		hardened::enable(Hardened::new().trusted_dirs([bin.as_path(), Path::new("/usr/bin"), Path::new("/bin")]).keep_env(["LANG"]));
+		
+		// The user's configuration file and tool exclusions are ignored
+		let reg = crate::tools::registry();
+		assert!(!reg.contains("evil"));
+		assert!(reg.warnings().is_empty());
+		assert!(reg.get_decompress_tool(CompressType::Gzip).is_some());
+		
+		// Environment variables other than those kept are refused, so do not reach the child
+		let tool = bin.join("tool");
+		let spec = FilterSpec::new(&tool, Vec::<String>::new()).env("LANG", "C").env("FOO", "injected");
+		let e = compress::open_read_filter(&spec, None::<Stdio>).unwrap_err();
+		assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+		let spec = FilterSpec::new(&tool, Vec::<String>::new()).env("LANG", "C");
+		let output = compress::open_read_filter(&spec, None::<Stdio>).unwrap().wait_with_output().unwrap();
+		assert_eq!(String::from_utf8(output.stdout).unwrap(), "C\n");
+	}
+	#[test]
+	fn test_search_path() {
+		use crate::tools::*;
+		use std::{fs, io::Read};
+		
+		let gzip = crate::path_utils::find_exec_path("gzip").expect("gzip not found");
+		let tdir = TmpDir::new();
+		let bin = tdir.mkpath("bin");
+		fs::create_dir(&bin).unwrap();
+		
+		// Tools are found after a rescan
+		let mut reg = ToolRegister::new();
+		reg.register(Tool::new("gzip").compress(Service::new(CompressType::Gzip))).unwrap();
+		reg.set_search_path([&bin]);
+		assert!(reg.get_compress_tool(CompressType::Gzip).is_none());
+		std::os::unix::fs::symlink(&gzip, bin.join("gzip")).unwrap();
+		assert!(reg.get_compress_tool(CompressType::Gzip).is_none());
+		reg.rescan();
+		assert_eq!(reg.get_compress_tool(CompressType::Gzip).and_then(|t| t.path()), Some(bin.join("gzip").as_path()));
+		
+		// Per builder search path
+		let empty = tdir.mkpath("empty");
+		fs::create_dir(&empty).unwrap();
+		let name = tdir.mkpath("test.gz");
+		assert!(CompressIo::new().path(&name).search_path([&empty]).writer().is_err());
+		let mut wrt = CompressIo::new().path(&name).search_path([&bin]).writer().expect("Could not make file");
+		write!(wrt, "Testing testing 123").unwrap();
+		wrt.finish().unwrap();
+		let mut s = String::new();
+		CompressIo::new().path(&name).search_path([&bin]).reader().unwrap().read_to_string(&mut s).unwrap();
+		assert_eq!(s, "Testing testing 123");
+		
+		// A rescan replaces the global register, which stays valid while held
+		let old = crate::tools::registry();
+		crate::tools::rescan();
+		assert!(!std::sync::Arc::ptr_eq(&old, &crate::tools::registry()));
+		assert!(old.get_decompress_tool(CompressType::Gzip).is_some());
+		assert!(crate::tools::decompress_tool(CompressType::Gzip).is_some());
+		
+		// References returned by the deprecated functions outlive a rescan
+		#[allow(deprecated)]
+		{
+			let tool = CompressType::Gzip.get_decompress_tool().unwrap();
+			crate::tools::rescan();
+			assert!(tool.path().is_some());
+		}
+	}
+	#[test]
+	fn test_reader_from() {
+		use std::io::{self, Cursor, Read};
+		use std::process::{Command, Stdio};
+		
+		let test_string = "Testing testing 123";
+		let mut child = Command::new("gzip").arg("-c").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+		child.stdin.take().unwrap().write_all(test_string.as_bytes()).unwrap();
+		let data = child.wait_with_output().unwrap().stdout;
+		
+		// Compressed and uncompressed (including short) sources
+		for (src, expected) in [(data.clone(), test_string), (test_string.as_bytes().to_vec(), test_string), (b"abc".to_vec(), "abc")] {
+			let mut rd = CompressIo::new().reader_from(Cursor::new(src)).unwrap();
+			let mut s = String::new();
+			rd.read_to_string(&mut s).unwrap();
+			assert_eq!(s, expected);
+			rd.finish().unwrap();
+		}
+		
+		// Errors from the source are returned by the reader
+		struct Failing(Cursor<Vec<u8>>);
+		impl Read for Failing {
+			fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+				match self.0.read(buf)? {
+					0 => Err(io::Error::other("source failed")),
+					n => Ok(n),
+				}
+			}
+		}
+		for src in [data, test_string.as_bytes().to_vec()] {
+			let mut rd = CompressIo::new().reader_from(Failing(Cursor::new(src.clone()))).unwrap();
+			let e = rd.read_to_end(&mut Vec::new()).unwrap_err();
+			assert_eq!(e.to_string(), "source failed");
+			
+			// Including when copied with compress::copy()
+			let tdir = TmpDir::new();
+			let mut rd = CompressIo::new().reader_from(Failing(Cursor::new(src))).unwrap();
+			let mut wrt = CompressIo::new().path(tdir.mkpath("test.txt")).writer().unwrap();
+			let e = crate::compress::copy(&mut rd, &mut wrt).unwrap_err();
+			assert_eq!(e.to_string(), "source failed");
+		}
+	}
+	#[test]
+	fn test_writer_into() {
+		use std::io::{self, Cursor, Read};
+		
+		let test_string = "Testing testing 123";
+		for ctype in [CompressType::Gzip, CompressType::NoFilter] {
+			let mut wrt = CompressIo::new().ctype(ctype).writer_into(Vec::new()).unwrap();
+			write!(wrt, "{}", test_string).unwrap();
+			let buf = wrt.finish().unwrap();
+			assert_eq!(buf == test_string.as_bytes(), ctype == CompressType::NoFilter);
+			let mut s = String::new();
+			CompressIo::new().reader_from(Cursor::new(buf)).unwrap().read_to_string(&mut s).unwrap();
+			assert_eq!(s, test_string);
+		}
+		
+		// Errors from the sink are reported in preference to the failure of the utility
+		struct Failing;
+		impl Write for Failing {
+			fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+				Err(io::Error::other("sink failed"))
+			}
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+		for ctype in [CompressType::Gzip, CompressType::NoFilter] {
+			let mut wrt = CompressIo::new().ctype(ctype).writer_into(Failing).unwrap();
+			let e = write!(wrt, "{}", test_string).and_then(|_| wrt.finish().map(|_| ())).unwrap_err();
+			assert_eq!(e.to_string(), "sink failed");
+		}
+	}
+	#[test]
+	fn test_transcode_stdin() {
+		use std::os::unix::io::AsRawFd;
+		
+		// Plain text on stdin is copied unchanged, including the bytes read to detect the format
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("out.txt");
+		let (rd, mut wr) = os_pipe::pipe().unwrap();
+		wr.write_all(b"hello world, plain text\n").unwrap();
+		drop(wr);
+		let res = unsafe {
+			let saved = libc::dup(0);
+			libc::dup2(rd.as_raw_fd(), 0);
+			let res = crate::compress::transcode(&CompressIo::new(), CompressIo::new().path(&name));
+			libc::dup2(saved, 0);
+			libc::close(saved);
+			res
+		};
+		res.unwrap().check().unwrap();
+		assert_eq!(std::fs::read_to_string(&name).unwrap(), "hello world, plain text\n");
+	}
+	#[test]
+	fn test_transcode_spawn_error() {
+		use std::{fs, os::unix::fs::PermissionsExt};
+		
+		// A utility that can not be executed
+		let tdir = TmpDir::new();
+		let bin = tdir.mkpath("bin");
+		fs::create_dir(&bin).unwrap();
+		let gzip = bin.join("gzip");
+		fs::write(&gzip, "#!/no/such/interpreter\n").unwrap();
+		fs::set_permissions(&gzip, fs::Permissions::from_mode(0o755)).unwrap();
+		
+		let input = tdir.mkpath("in.txt");
+		fs::write(&input, "Testing testing 123").unwrap();
+		let output = tdir.mkpath("out.gz");
+		let res = crate::compress::transcode(CompressIo::new().path(&input), CompressIo::new().path(&output).search_path([&bin]));
+		assert!(res.is_err());
+		assert!(!output.exists());
+	}
+	#[test]
+	fn test_kind() {
+		use crate::compress::{ReaderKind, WriterKind};
+		
+		let tdir = TmpDir::new();
+		let name = tdir.mkpath("test.gz");
+		let wrt = CompressIo::new().path(&name).writer().unwrap();
+		assert!(matches!(wrt.get_ref(), WriterKind::Child(Some(_), Some(_))));
+		wrt.finish().unwrap();
+		let rdr = CompressIo::new().path(&name).reader().unwrap();
+		assert!(matches!(rdr.get_ref(), ReaderKind::Child(Some(_), _)));
+		let rdr = CompressIo::new().path(&name).ctype(CompressType::NoFilter).reader().unwrap();
+		assert!(matches!(rdr.get_ref(), ReaderKind::File(_)));
+	}
 }
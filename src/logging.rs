@@ -0,0 +1,33 @@
+//! Internal logging macros.  With the `log` feature these forward to the [log] crate, otherwise
+//! they expand to nothing (the arguments are still type checked, so variables used only for
+//! logging do not generate warnings).
+//!
+//! [log]: https://docs.rs/log
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! trace {
+    ($($arg:tt)*) => { log::trace!($($arg)*) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
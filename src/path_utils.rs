@@ -16,20 +16,37 @@ fn access(p: &Path) -> Result<bool, String> {
     unsafe { Ok(libc::access(cstr.as_ptr(), libc::X_OK) == 0) }
 }
 
+/// Returns true if `p` exists and is executable by the current user
+pub fn is_executable<P: AsRef<Path>>(p: P) -> bool {
+    let p = p.as_ref();
+    p.exists() && matches!(access(p), Ok(true))
+}
+
+/// Searches for an executable `prog` in the directories in `$PATH` or, in
+/// [`hardened`](crate::hardened) mode, in the trusted directories.
 pub fn find_exec_path<S: AsRef<OsStr>>(prog: S) -> Option<PathBuf> {
+    if let Some(dirs) = crate::hardened::search_dirs() {
+        return find_exec_path_in(prog, &dirs);
+    }
     let search_path =
         env::var_os("PATH").unwrap_or_else(|| OsString::from("/usr/bin:/usr/local/bin"));
     for path in env::split_paths(&search_path) {
         let candidate = path.join(prog.as_ref());
-        if candidate.exists() {
-            if let Ok(true) = access(&candidate) {
-                return Some(candidate);
-            }
+        if is_executable(&candidate) {
+            return Some(candidate);
         }
     }
     None
 }
 
+/// Searches for an executable `prog` in the directories `dirs`.  In
+/// [`hardened`](crate::hardened) mode, utilities outside the trusted locations are ignored.
+pub fn find_exec_path_in<S: AsRef<OsStr>, P: AsRef<Path>>(prog: S, dirs: &[P]) -> Option<PathBuf> {
+    dirs.iter()
+        .map(|d| d.as_ref().join(prog.as_ref()))
+        .find(|p| is_executable(p) && crate::hardened::check_program(p).is_ok())
+}
+
 fn add_ext_to_path<S: AsRef<OsStr>>(p: &Path, ext: S) -> PathBuf {
     let p: &OsStr = p.as_ref();
     let mut buf = p.to_os_string();
@@ -87,13 +104,21 @@ pub fn check_read_ctype<P: AsRef<Path>>(
     buf: Option<&mut CheckBuf>,
 ) -> io::Result<CompressType> {
     if matches!(ctype, CompressType::Unknown) {
-        if let Some(s) = name {
-            guess_ctype_from_file(s.as_ref(), buf)
+        let name = name.as_ref().map(|s| s.as_ref());
+        let ct = if let Some(s) = name {
+            guess_ctype_from_file(s, buf)
         } else if let Some(b) = buf {
             guess_ctype_from_handle(&mut stdin(), b)
         } else {
             Ok(CompressType::NoFilter)
-        }
+        }?;
+        trace!(
+            "Detected compression type {} for {}",
+            ct,
+            name.map(|p| p.display().to_string())
+                .unwrap_or_else(|| "stdin".to_owned())
+        );
+        Ok(ct)
     } else {
         if let Some(b) = buf {
             b.clear()
@@ -0,0 +1,603 @@
+//! Management of child processes
+//!
+//! The compression utilities spawned by readers and writers are tracked so that they can be
+//! terminated with [`shutdown_all_children`].  Two further settings, both off by default, help
+//! to ensure that the utilities do not outlive the calling process:
+//!
+//! * On Linux, each child process can be asked (using `prctl(PR_SET_PDEATHSIG)`) to be killed
+//!   when its parent exits, so that a crash or `abort()` does not leave compression utilities
+//!   running.  Note that the signal is sent when the *thread* that created the reader or writer
+//!   exits, not when the process exits.  A writer created in a short lived or pooled thread
+//!   (for example by `tokio::task::spawn_blocking` or in a `rayon` worker) would have its
+//!   utility killed when that thread ends, truncating the output, so this should only be
+//!   enabled where readers and writers are created in long lived threads.  It is never applied
+//!   to writers created with [`CompressIo::no_wait`], as these are reaped in the background.
+//!   This is enabled with [`CompressIo::kill_on_parent_death`] or globally with
+//!   [`set_kill_on_parent_death`].
+//! * Each child process can be placed in its own process group, so that a `SIGINT` from the
+//!   terminal (i.e., Control-C) is delivered only to the calling process.  The application can
+//!   then shut down in an orderly way, calling [`shutdown_all_children`] to terminate any child
+//!   processes that are still running.  Child processes in a background process group are
+//!   stopped if they read from the terminal, or write to it while `tostop` is set.  This is
+//!   enabled with [`CompressIo::process_group`] or globally with [`set_process_group`].
+//!
+//! The same settings can be made for the `async` readers and writers (see [`AsyncCompressIo`]),
+//! whose child processes are however not tracked by [`shutdown_all_children`].  Nor are the child processes returned by
+//! [`open_read_filter`] and [`open_write_filter`], as these are reaped by the caller.
+//!
+//! The child processes of writers created with [`CompressIo::no_wait`] are waited on by a
+//! background thread, so they do not remain as zombie processes after they have finished.  A
+//! [`Completion`] handle, available from [`Writer::completion`], can be used to find out when
+//! the child process has finished (and so the output file is complete) and whether it
+//! succeeded.  [`background_children`] returns the handles for all such processes that are
+//! still running, and [`wait_background`] waits for all of them to finish.
+//!
+//! The scheduling priority, I/O priority, CPU affinity and resource limits of the compression
+//! utilities can be set using [`Resources`], either for a particular [`CompressIo`] with
+//! [`CompressIo::resources`] or globally with [`set_default_resources`].
+//!
+//! By default, messages written to `stderr` by the compression utilities (for example warnings
+//! about trailing garbage in compressed files) go to the `stderr` of the calling process.  This
+//! can be changed with [`CompressIo::stderr`] to discard the messages, to capture them so that
+//! they are included in any error reported when a utility fails, or to pass them line by line
+//! to a callback (see [`StderrPolicy`]).
+//!
+//! # Examples
+//!
+//! Terminate child processes when the user presses Control-C, using the [ctrlc] crate to run
+//! the handler (which should not be called from within a signal handler).
+//!
+//! ```ignore
+//! ctrlc::set_handler(|| {
+//!     compress_io::process::shutdown_all_children();
+//!     std::process::exit(130)
+//! })?;
+//! ```
+//!
+//! [`CompressIo::kill_on_parent_death`]: crate::compress::CompressIo::kill_on_parent_death
+//! [`CompressIo::process_group`]: crate::compress::CompressIo::process_group
+//! [`CompressIo::no_wait`]: crate::compress::CompressIo::no_wait
+//! [`CompressIo::stderr`]: crate::compress::CompressIo::stderr
+//! [`CompressIo::resources`]: crate::compress::CompressIo::resources
+//! [`CompressIo`]: crate::compress::CompressIo
+//! [`AsyncCompressIo`]: crate::async::compress::AsyncCompressIo
+//! [`Writer::completion`]: crate::compress::Writer::completion
+//! [`open_read_filter`]: crate::compress::open_read_filter
+//! [`open_write_filter`]: crate::compress::open_write_filter
+//! [ctrlc]: https://docs.rs/ctrlc
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufRead, BufReader, Error, Read},
+    os::unix::process::CommandExt,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::compress::check_status;
+use crate::stats::wait_child;
+
+mod resources;
+pub use resources::{IoPriority, Resources};
+
+static KILL_ON_PARENT_DEATH: AtomicBool = AtomicBool::new(false);
+static PROCESS_GROUP: AtomicBool = AtomicBool::new(false);
+static DEFAULT_RESOURCES: Mutex<Option<Resources>> = Mutex::new(None);
+
+// A live child process
+struct Registered {
+    pid: u32,
+    // The child leads its own process group
+    group: bool,
+    start: Instant,
+}
+
+static CHILDREN: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+
+// Children being waited on by background threads
+static BACKGROUND: Mutex<Vec<Completion>> = Mutex::new(Vec::new());
+
+/// Sets the default for whether child processes are killed when the thread creating them exits
+/// (default false).  Only affects [`SpawnOptions`] created after the call.
+pub fn set_kill_on_parent_death(x: bool) {
+    KILL_ON_PARENT_DEATH.store(x, Ordering::Relaxed)
+}
+
+/// Sets the default for whether child processes are placed in their own process group
+/// (default false).  Only affects [`SpawnOptions`] created after the call.
+pub fn set_process_group(x: bool) {
+    PROCESS_GROUP.store(x, Ordering::Relaxed)
+}
+
+/// Type of a callback receiving the lines written to `stderr` by a compression utility
+pub type StderrFn = dyn Fn(&str) + Send + Sync;
+
+/// How the output written to `stderr` by a compression utility is handled
+#[derive(Clone, Default)]
+pub enum StderrPolicy {
+    /// Output goes to the `stderr` of the calling process
+    #[default]
+    Inherit,
+    /// Output is discarded
+    Discard,
+    /// Up to the given number of bytes (the last bytes written) are kept and added to the
+    /// error returned if the utility fails.  Other output is discarded.  Failures are only
+    /// reported by [`Reader::finish`], [`Writer::finish`] and [`transcode`]; in other cases
+    /// (including writers created with `no_wait`) the output is simply discarded.  This policy
+    /// is not supported by the `async` readers and writers.
+    ///
+    /// [`Reader::finish`]: crate::compress::Reader::finish
+    /// [`Writer::finish`]: crate::compress::Writer::finish
+    /// [`transcode`]: crate::compress::transcode
+    Capture(usize),
+    /// Each line of output (without the line terminator) is passed to the callback.  The
+    /// callback is called from a background thread.
+    Forward(Arc<StderrFn>),
+}
+
+impl fmt::Debug for StderrPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inherit => write!(f, "Inherit"),
+            Self::Discard => write!(f, "Discard"),
+            Self::Capture(n) => f.debug_tuple("Capture").field(n).finish(),
+            Self::Forward(_) => write!(f, "Forward(..)"),
+        }
+    }
+}
+
+impl StderrPolicy {
+    /// Forward each line of output to `callback`
+    pub fn forward<F>(callback: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        Self::Forward(Arc::new(callback))
+    }
+
+    /// Forward each line of output to the [log](https://docs.rs/log) crate as a record at the
+    /// `warn` level with the target `compress_io::stderr`
+    ///
+    /// *Only available with the `log` feature*
+    #[cfg(feature = "log")]
+    pub fn log() -> Self {
+        Self::forward(|s| log::warn!(target: "compress_io::stderr", "{}", s))
+    }
+
+    fn stdio(&self) -> Stdio {
+        match self {
+            Self::Inherit => Stdio::inherit(),
+            Self::Discard => Stdio::null(),
+            Self::Capture(_) | Self::Forward(_) => Stdio::piped(),
+        }
+    }
+}
+
+/// Sets the default resource controls for child processes (see [`Resources`]).  Only affects
+/// [`SpawnOptions`] created after the call.
+pub fn set_default_resources(resources: Resources) {
+    *DEFAULT_RESOURCES.lock().unwrap() = Some(resources)
+}
+
+/// Options applied when spawning a compression utility.  The defaults are taken from the
+/// global settings ([`set_kill_on_parent_death`], [`set_process_group`] and
+/// [`set_default_resources`]) at the time of creation.
+#[derive(Debug, Clone)]
+pub struct SpawnOptions {
+    kill_on_parent_death: bool,
+    process_group: bool,
+    stderr: StderrPolicy,
+    resources: Resources,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self {
+            kill_on_parent_death: KILL_ON_PARENT_DEATH.load(Ordering::Relaxed),
+            process_group: PROCESS_GROUP.load(Ordering::Relaxed),
+            stderr: StderrPolicy::default(),
+            resources: DEFAULT_RESOURCES
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl SpawnOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Kill the child process if the thread spawning it exits (Linux only; ignored on other
+    /// platforms)
+    pub fn kill_on_parent_death(mut self, x: bool) -> Self {
+        self.kill_on_parent_death = x;
+        self
+    }
+
+    /// Place the child process in its own process group
+    pub fn process_group(mut self, x: bool) -> Self {
+        self.process_group = x;
+        self
+    }
+
+    /// Set how the output written to `stderr` by the child process is handled
+    pub fn stderr(mut self, policy: StderrPolicy) -> Self {
+        self.stderr = policy;
+        self
+    }
+
+    /// Set the resource controls for the child process
+    pub fn resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    pub fn get_kill_on_parent_death(&self) -> bool {
+        self.kill_on_parent_death
+    }
+
+    pub fn get_process_group(&self) -> bool {
+        self.process_group
+    }
+
+    pub fn get_stderr(&self) -> &StderrPolicy {
+        &self.stderr
+    }
+
+    pub fn get_resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Applies the options (and the [`hardened`](crate::hardened) mode settings) to `cmd`.
+    /// Fails if the program is refused in hardened mode.
+    pub(crate) fn apply(&self, cmd: &mut Command) -> io::Result<()> {
+        crate::hardened::apply(cmd)?;
+        cmd.stderr(self.stderr.stdio());
+        if self.process_group {
+            cmd.process_group(0);
+        }
+        #[cfg(target_os = "linux")]
+        if self.kill_on_parent_death {
+            let ppid = unsafe { libc::getpid() };
+            // Only async-signal-safe system calls are made between fork and exec
+            unsafe {
+                cmd.pre_exec(move || {
+                    if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    // The parent may have exited before prctl() was called
+                    if libc::getppid() != ppid {
+                        return Err(io::Error::from_raw_os_error(libc::ESRCH));
+                    }
+                    Ok(())
+                });
+            }
+        }
+        self.resources.apply(cmd);
+        Ok(())
+    }
+}
+
+/// Handles the output written to `stderr` by a child process in a background thread
+#[derive(Debug)]
+pub(crate) struct StderrCollector {
+    thread: Option<JoinHandle<()>>,
+    captured: Option<Arc<Mutex<VecDeque<u8>>>>,
+}
+
+impl StderrCollector {
+    /// Starts handling the output from `rd` (the `stderr` of a child process) according to
+    /// `policy`.  Returns `None` if the policy does not require the output to be read.
+    pub(crate) fn new<R: Read + Send + 'static>(rd: R, policy: &StderrPolicy) -> Option<Self> {
+        let (thread, captured) = match policy {
+            StderrPolicy::Capture(limit) => {
+                let limit = *limit;
+                let captured = Arc::new(Mutex::new(VecDeque::new()));
+                let buf = captured.clone();
+                let th = thread::Builder::new()
+                    .name("compress-io-stderr".to_owned())
+                    .spawn(move || capture(rd, limit, &buf));
+                (th, Some(captured))
+            }
+            StderrPolicy::Forward(callback) => {
+                let callback = callback.clone();
+                let th = thread::Builder::new()
+                    .name("compress-io-stderr".to_owned())
+                    .spawn(move || forward(rd, &*callback));
+                (th, None)
+            }
+            _ => return None,
+        };
+        // If the thread can not be started, the output is lost
+        Some(Self {
+            thread: thread.ok(),
+            captured,
+        })
+    }
+
+    /// Waits for the child process to close `stderr`, returning the captured output (if any)
+    pub(crate) fn finish(mut self) -> Option<String> {
+        if let Some(th) = self.thread.take() {
+            let _ = th.join();
+        }
+        let captured = self.captured.take()?;
+        let mut buf = captured.lock().unwrap();
+        let s = String::from_utf8_lossy(buf.make_contiguous());
+        let s = s.trim();
+        if s.is_empty() {
+            None
+        } else {
+            Some(s.to_owned())
+        }
+    }
+}
+
+// Keeps the last `limit` bytes read from `rd`
+fn capture<R: Read>(mut rd: R, limit: usize, captured: &Mutex<VecDeque<u8>>) {
+    let mut buf = [0; 4096];
+    while let Ok(n) = rd.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        let mut v = captured.lock().unwrap();
+        v.extend(&buf[..n]);
+        let excess = v.len().saturating_sub(limit);
+        v.drain(..excess);
+    }
+}
+
+fn forward<R: Read>(rd: R, callback: &StderrFn) {
+    let mut rd = BufReader::new(rd);
+    let mut line = Vec::new();
+    while let Ok(n) = rd.read_until(b'\n', &mut line) {
+        if n == 0 {
+            break;
+        }
+        let s = String::from_utf8_lossy(&line);
+        callback(s.trim_end_matches(['\n', '\r']));
+        line.clear()
+    }
+}
+
+/// Adds the output captured from `stderr` (if any) to an error
+pub(crate) fn add_stderr(e: io::Error, stderr: Option<String>) -> io::Error {
+    match stderr {
+        Some(s) => Error::new(e.kind(), format!("{}: {}", e, s)),
+        None => e,
+    }
+}
+
+/// Records a newly spawned child so that it can be terminated by [`shutdown_all_children`]
+pub(crate) fn register(child: &Child, opts: &SpawnOptions) {
+    CHILDREN.lock().unwrap().push(Registered {
+        pid: child.id(),
+        group: opts.process_group,
+        start: Instant::now(),
+    })
+}
+
+/// Removes a child from the registry.  Must be called *before* the child is reaped to avoid
+/// signalling an unrelated process that has been given the same pid.  Returns the time the
+/// child was registered.
+pub(crate) fn deregister(child: &Child) -> Option<Instant> {
+    let pid = child.id();
+    let mut children = CHILDREN.lock().unwrap();
+    let ix = children.iter().position(|c| c.pid == pid)?;
+    Some(children.swap_remove(ix).start)
+}
+
+/// Logs the exit of a child process (with the `log` feature)
+pub(crate) fn log_exit(pid: u32, status: ExitStatus, start: Option<Instant>) {
+    debug!(
+        "Child process {} exited ({}) after {}",
+        pid,
+        status,
+        start
+            .map(|t| format!("{:.3}s", t.elapsed().as_secs_f64()))
+            .unwrap_or_else(|| "unknown time".to_owned())
+    )
+}
+
+/// Deregisters and waits for a child process
+pub(crate) fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+    let start = deregister(child);
+    let status = child.wait()?;
+    log_exit(child.id(), status, start);
+    Ok(status)
+}
+
+/// Sends `SIGTERM` to all running compression utilities spawned by readers and writers (and
+/// to their process groups where these were created), returning the number of child
+/// processes signalled.  Subsequent reads and writes involving these processes will fail.
+///
+/// This function takes a lock, so must not be called directly from a signal handler.
+pub fn shutdown_all_children() -> usize {
+    let children = CHILDREN.lock().unwrap();
+    for c in children.iter() {
+        let pid = c.pid as libc::pid_t;
+        unsafe {
+            if c.group {
+                libc::killpg(pid, libc::SIGTERM);
+            } else {
+                libc::kill(pid, libc::SIGTERM);
+            }
+        }
+    }
+    children.len()
+}
+
+#[derive(Default)]
+struct CompletionInner {
+    status: Mutex<Option<io::Result<ExitStatus>>>,
+    cond: Condvar,
+}
+
+/// A handle to a child process that is being waited on in the background.  Clones of the
+/// handle refer to the same child process.
+#[derive(Clone)]
+pub struct Completion {
+    pid: u32,
+    inner: Arc<CompletionInner>,
+}
+
+impl fmt::Debug for Completion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Completion")
+            .field("pid", &self.pid)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+// io::Error is not Clone, so errors are copied for each caller
+fn copy_result(r: &io::Result<ExitStatus>) -> io::Result<ExitStatus> {
+    match r {
+        Ok(s) => Ok(*s),
+        Err(e) => Err(Error::new(e.kind(), e.to_string())),
+    }
+}
+
+impl Completion {
+    fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            inner: Arc::new(CompletionInner::default()),
+        }
+    }
+
+    // The status is set while holding the lock on the background list, so a finished child is
+    // never seen in the list
+    fn set(&self, res: io::Result<ExitStatus>) {
+        let mut bg = BACKGROUND.lock().unwrap();
+        bg.retain(|c| !Arc::ptr_eq(&c.inner, &self.inner));
+        *self.inner.status.lock().unwrap() = Some(res);
+        self.inner.cond.notify_all()
+    }
+
+    /// Process id of the child process
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns true if the child process has finished
+    pub fn is_finished(&self) -> bool {
+        self.inner.status.lock().unwrap().is_some()
+    }
+
+    /// Returns the exit status of the child process if it has finished, otherwise `None`
+    pub fn try_wait(&self) -> io::Result<Option<ExitStatus>> {
+        self.inner
+            .status
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(copy_result)
+            .transpose()
+    }
+
+    /// Waits for the child process to finish, returning its exit status
+    pub fn wait(&self) -> io::Result<ExitStatus> {
+        let mut status = self.inner.status.lock().unwrap();
+        loop {
+            if let Some(r) = status.as_ref() {
+                return copy_result(r);
+            }
+            status = self.inner.cond.wait(status).unwrap();
+        }
+    }
+
+    /// Waits for up to `timeout` for the child process to finish, returning `None` if it has
+    /// not finished in time
+    pub fn wait_timeout(&self, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+        let deadline = Instant::now() + timeout;
+        let mut status = self.inner.status.lock().unwrap();
+        loop {
+            if let Some(r) = status.as_ref() {
+                return copy_result(r).map(Some);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            status = self
+                .inner
+                .cond
+                .wait_timeout(status, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Waits for the child process to finish, returning an error if it failed
+    pub fn check(&self) -> io::Result<()> {
+        check_status(self.wait()?)
+    }
+}
+
+// Waits for a child to exit *without* reaping it
+fn wait_exit(pid: u32) -> io::Result<()> {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let flags = libc::WEXITED | libc::WNOWAIT;
+        if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) } == 0 {
+            return Ok(());
+        }
+        let e = Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// Waits for `child` in a background thread, returning a handle to the child process.  The
+/// child remains registered (so it can be terminated by [`shutdown_all_children`]) until it has
+/// exited.
+pub(crate) fn reap_in_background(child: Child) -> Completion {
+    let completion = Completion::new(child.id());
+    BACKGROUND.lock().unwrap().push(completion.clone());
+    let c = completion.clone();
+    let res = thread::Builder::new()
+        .name("compress-io-reaper".to_owned())
+        .spawn(move || {
+            let res = wait_exit(c.pid).and_then(|_| wait_child(child).map(|(s, _)| s));
+            c.set(res)
+        });
+    if let Err(e) = res {
+        completion.set(Err(Error::other(format!(
+            "Couldn't start thread to wait for child process: {}",
+            e
+        ))))
+    }
+    completion
+}
+
+/// Returns handles to the child processes of [`CompressIo::no_wait`] writers that are still
+/// running
+///
+/// [`CompressIo::no_wait`]: crate::compress::CompressIo::no_wait
+pub fn background_children() -> Vec<Completion> {
+    BACKGROUND.lock().unwrap().clone()
+}
+
+/// Waits for all child processes of [`CompressIo::no_wait`] writers to finish.  Returns an error
+/// if any of the processes that were running when this function was called failed.
+///
+/// [`CompressIo::no_wait`]: crate::compress::CompressIo::no_wait
+pub fn wait_background() -> io::Result<()> {
+    let mut res = Ok(());
+    for c in background_children() {
+        if let Err(e) = c.check() {
+            res = res.and(Err(e))
+        }
+    }
+    res
+}
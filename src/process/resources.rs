@@ -0,0 +1,184 @@
+//! Resource controls (scheduling priority, I/O priority, CPU affinity and resource limits)
+//! applied to child processes between `fork` and `exec`.
+
+use std::{io, os::unix::process::CommandExt, process::Command};
+
+/// I/O scheduling class and priority level (see `ioprio_set(2)`).  Levels are from 0 (highest
+/// priority) to 7 (lowest priority).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IoPriority {
+    RealTime(u8),
+    BestEffort(u8),
+    Idle,
+}
+
+impl IoPriority {
+    #[cfg(target_os = "linux")]
+    fn value(&self) -> libc::c_int {
+        const CLASS_SHIFT: libc::c_int = 13;
+        let (class, level) = match self {
+            Self::RealTime(l) => (1, *l),
+            Self::BestEffort(l) => (2, *l),
+            Self::Idle => (3, 0),
+        };
+        (class << CLASS_SHIFT) | level.min(7) as libc::c_int
+    }
+}
+
+/// Resource controls for the compression utilities.  By default no controls are applied.
+///
+/// The nice level and resource limits are applied on all platforms; the I/O priority and CPU
+/// affinity are only applied on Linux.  If a setting can not be applied (for example, because
+/// the nice level is lower than that of the calling process and the process lacks the
+/// privileges to raise its priority) then spawning the utility fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use compress_io::{compress::CompressIo, process::{IoPriority, Resources}};
+///
+/// // Run xz at low priority on the first two CPUs
+/// let res = Resources::new()
+///   .nice(19)
+///   .io_priority(IoPriority::Idle)
+///   .cpu_affinity([0, 1]);
+/// let mut wrt = CompressIo::new().path("foo.xz").resources(res).writer()
+///   .expect("Error opening output file");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resources {
+    nice: Option<i32>,
+    io_priority: Option<IoPriority>,
+    cpu_affinity: Option<Vec<usize>>,
+    memory_limit: Option<u64>,
+    cpu_time_limit: Option<u64>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the nice level (from -20 to 19) of the utility
+    pub fn nice(mut self, level: i32) -> Self {
+        self.nice = Some(level);
+        self
+    }
+
+    /// Sets the I/O scheduling class and priority of the utility (Linux only)
+    pub fn io_priority(mut self, prio: IoPriority) -> Self {
+        self.io_priority = Some(prio);
+        self
+    }
+
+    /// Restricts the utility to run on the given CPUs (Linux only)
+    pub fn cpu_affinity<I: IntoIterator<Item = usize>>(mut self, cpus: I) -> Self {
+        self.cpu_affinity = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Limits the address space of the utility to `bytes` (`RLIMIT_AS`)
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Limits the CPU time used by the utility to `secs` seconds (`RLIMIT_CPU`).  The utility
+    /// is killed if the limit is exceeded.
+    pub fn cpu_time_limit(mut self, secs: u64) -> Self {
+        self.cpu_time_limit = Some(secs);
+        self
+    }
+
+    pub fn get_nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    pub fn get_io_priority(&self) -> Option<IoPriority> {
+        self.io_priority
+    }
+
+    pub fn get_cpu_affinity(&self) -> Option<&[usize]> {
+        self.cpu_affinity.as_deref()
+    }
+
+    pub fn get_memory_limit(&self) -> Option<u64> {
+        self.memory_limit
+    }
+
+    pub fn get_cpu_time_limit(&self) -> Option<u64> {
+        self.cpu_time_limit
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies the resource controls to `cmd`
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        if self.is_empty() {
+            return;
+        }
+        let nice = self.nice;
+        let limits: Vec<_> = [
+            (libc::RLIMIT_AS, self.memory_limit),
+            (libc::RLIMIT_CPU, self.cpu_time_limit),
+        ]
+        .into_iter()
+        .filter_map(|(r, x)| {
+            x.map(|x| {
+                let x = x as libc::rlim_t;
+                (
+                    r,
+                    libc::rlimit {
+                        rlim_cur: x,
+                        rlim_max: x,
+                    },
+                )
+            })
+        })
+        .collect();
+        #[cfg(target_os = "linux")]
+        let io_priority = self.io_priority.map(|p| p.value());
+        // The CPU set is prepared here as nothing can be allocated after fork()
+        #[cfg(target_os = "linux")]
+        let cpu_set = self.cpu_affinity.as_ref().map(|cpus| {
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for c in cpus.iter().filter(|c| **c < libc::CPU_SETSIZE as usize) {
+                unsafe { libc::CPU_SET(*c, &mut set) }
+            }
+            set
+        });
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(n) = nice {
+                    // The return value of setpriority() is not ambiguous, unlike nice()
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, n) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for (r, lim) in limits.iter() {
+                    if libc::setrlimit(*r, lim) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                {
+                    if let Some(p) = io_priority {
+                        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+                        if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, p) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    if let Some(set) = cpu_set.as_ref() {
+                        let size = std::mem::size_of::<libc::cpu_set_t>();
+                        if libc::sched_setaffinity(0, size, set) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
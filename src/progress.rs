@@ -0,0 +1,185 @@
+//! Progress reporting for long running reads and writes
+//!
+//! A progress callback can be registered with [`CompressIo::progress`].  Readers and writers
+//! generated from the [`CompressIo`] will then call the callback periodically (by default at most
+//! every 500ms, see [`CompressIo::progress_interval`]) while data is being read or written, and
+//! once more when the reader or writer is finished.  The callback is passed a [`Progress`]
+//! giving the number of (uncompressed) bytes processed and, for file inputs and outputs, the
+//! number of compressed bytes read from or written to the file.  For file inputs the total size
+//! of the input file is also given, so the fraction of the input that has been processed can be
+//! calculated even though the decompression is performed by an external process.
+//!
+//! The callback is called from the thread performing the reads or writes, so should not block.
+//! To process progress reports in another thread, the callback can send the [`Progress`] down a
+//! channel.
+//!
+//! With the `progress-bar` feature, [`CompressIo::progress_bar`] can be used instead to display
+//! an [indicatif] progress bar on `stderr` showing the position in the input file against
+//! the file size (or the amount of data written for writers), the throughput and the
+//! compression utility in use.  A callback driving an existing [indicatif] progress bar (for
+//! example one that is part of an `indicatif::MultiProgress`) can be made with
+//! [`bar_callback`].
+//!
+//! [indicatif]: https://docs.rs/indicatif
+//! [`CompressIo::progress_bar`]: crate::compress::CompressIo::progress_bar
+//! [`CompressIo`]: crate::compress::CompressIo
+//! [`CompressIo::progress`]: crate::compress::CompressIo::progress
+//! [`CompressIo::progress_interval`]: crate::compress::CompressIo::progress_interval
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "progress-bar")]
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+
+pub(crate) const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A progress report passed to a progress callback
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Progress {
+    /// Uncompressed bytes read or written
+    pub bytes: u64,
+    /// Compressed bytes read from or written to a file.  `None` if the input or output is not a
+    /// file.
+    pub compressed_bytes: Option<u64>,
+    /// Size of the input file.  `None` for writers, or if the input is not a regular file.
+    pub compressed_total: Option<u64>,
+    /// Time since the reader or writer was created
+    pub elapsed: Duration,
+    /// True for the final report when the reader or writer is finished
+    pub finished: bool,
+}
+
+impl Progress {
+    /// Fraction of the input file that has been consumed (between 0 and 1), if known
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.compressed_bytes, self.compressed_total) {
+            (Some(c), Some(t)) if t > 0 => Some((c as f64 / t as f64).min(1.0)),
+            (Some(_), Some(_)) => Some(1.0),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", self.bytes)?;
+        if let Some(x) = self.fraction() {
+            write!(f, " ({:.1}%)", x * 100.0)?
+        }
+        write!(f, " in {:.1}s", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Type of a progress callback
+pub type ProgressFn = dyn Fn(&Progress) + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct ProgressHook {
+    callback: Arc<ProgressFn>,
+    interval: Duration,
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProgressHook")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProgressHook {
+    pub(crate) fn new(callback: Arc<ProgressFn>) -> Self {
+        Self {
+            callback,
+            interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+
+    pub(crate) fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval
+    }
+}
+
+// Progress hook attached to a reader or writer
+#[derive(Debug)]
+pub(crate) struct ProgressState {
+    hook: ProgressHook,
+    last: Instant,
+    total: Option<u64>,
+}
+
+impl ProgressState {
+    pub(crate) fn new(hook: ProgressHook, total: Option<u64>) -> Self {
+        Self {
+            hook,
+            last: Instant::now(),
+            total,
+        }
+    }
+
+    pub(crate) fn due(&self) -> bool {
+        self.last.elapsed() >= self.hook.interval
+    }
+
+    pub(crate) fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    pub(crate) fn report(&mut self, p: &Progress) {
+        self.last = Instant::now();
+        (self.hook.callback)(p)
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+const BAR_TEMPLATE: &str =
+    "{prefix} [{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}";
+
+#[cfg(feature = "progress-bar")]
+const SPINNER_TEMPLATE: &str =
+    "{spinner} {prefix} [{elapsed_precise}] {bytes} ({bytes_per_sec}) {msg}";
+
+/// Creates a progress bar suitable for use with [`bar_callback`].  If `total` (the size of the
+/// input file) is known a bar is displayed, otherwise a spinner.  The prefix shows the
+/// compression utility (if any) and `name` (normally the file path).
+#[cfg(feature = "progress-bar")]
+pub fn new_bar(tool: Option<&str>, name: &str, total: Option<u64>) -> ProgressBar {
+    let (bar, template) = match total {
+        Some(n) => (ProgressBar::new(n), BAR_TEMPLATE),
+        None => (ProgressBar::new_spinner(), SPINNER_TEMPLATE),
+    };
+    let style = ProgressStyle::with_template(template).expect("Invalid progress bar template");
+    bar.set_prefix(match tool {
+        Some(t) => format!("{} {}", t, name),
+        None => name.to_owned(),
+    });
+    bar.with_style(style)
+}
+
+/// Returns a progress callback that updates `bar`.  If `bar` has a length then the position
+/// is set to the compressed bytes consumed from the input file, otherwise to the number of
+/// (uncompressed) bytes processed.  The bar is finished with the final progress report.
+#[cfg(feature = "progress-bar")]
+pub fn bar_callback(bar: ProgressBar) -> impl Fn(&Progress) + Send + Sync + 'static {
+    move |p| {
+        match (p.compressed_bytes, bar.length()) {
+            (Some(c), Some(_)) => {
+                bar.set_position(c);
+                bar.set_message(format!("{} uncompressed", HumanBytes(p.bytes)))
+            }
+            (c, _) => {
+                bar.set_position(p.bytes);
+                if let Some(c) = c {
+                    bar.set_message(format!("{} compressed", HumanBytes(c)))
+                }
+            }
+        }
+        if p.finished {
+            bar.finish()
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Writing a stream to a series of (possibly compressed) files of limited size
+//!
+//! A [`SplitWriter`] writes to a sequence of output files, starting a new file once the current
+//! file has received a given number of uncompressed bytes or records.  Output files are only
+//! split at record boundaries (by default at the end of a line).  The names of the output files
+//! are generated from the path of the [`CompressIo`] configuration by inserting a part number
+//! before the final extension, so `part.gz` gives `part-0000.gz`, `part-0001.gz` etc.  All other
+//! settings (compression type, threads, level etc.) are taken from the [`CompressIo`]
+//! configuration and applied to each part.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::Write;
+//! use compress_io::{
+//!   compress::CompressIo,
+//!   split::{SplitLimit, SplitWriter},
+//! };
+//!
+//! fn main() -> std::io::Result<()> {
+//!   let mut wrt = SplitWriter::new(CompressIo::new().path("part.gz"), SplitLimit::Records(1000000))?;
+//!   for i in 0..10000000 {
+//!     writeln!(wrt, "Line {}", i)?;
+//!   }
+//!   for p in wrt.finish()? {
+//!     println!("{}", p.display())
+//!   }
+//!   Ok(())
+//! }
+//! ```
+
+use std::{
+    ffi::OsString,
+    io::{self, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::compress::{CompressIo, Writer};
+
+/// When to start a new output file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SplitLimit {
+    /// Start a new file at the first record boundary once this many uncompressed bytes have been
+    /// written to the current file.  The output files will therefore normally be slightly
+    /// larger than this limit (before compression).
+    Bytes(u64),
+    /// Start a new file after this many records
+    Records(u64),
+}
+
+/// A writer that splits its output over several files.  See the
+/// [module level documentation](crate::split) for more details.
+#[derive(Debug)]
+pub struct SplitWriter {
+    cfg: CompressIo,
+    base: PathBuf,
+    limit: SplitLimit,
+    delimiter: u8,
+    current: Option<Writer>,
+    bytes: u64,
+    records: u64,
+    at_boundary: bool,
+    paths: Vec<PathBuf>,
+    error: Option<io::Error>,
+}
+
+impl SplitWriter {
+    /// Creates a new [`SplitWriter`] using the settings from `cfg`.  Returns an error if `cfg`
+    /// does not have a path set, or if the limit is zero.  No output file is created until
+    /// data is written.
+    pub fn new(cfg: &CompressIo, limit: SplitLimit) -> io::Result<Self> {
+        let base = cfg.get_path().map(|p| p.to_owned()).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "A path is required for a split writer",
+            )
+        })?;
+        if matches!(limit, SplitLimit::Bytes(0) | SplitLimit::Records(0)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Split writer limit must be greater than zero",
+            ));
+        }
+        Ok(Self {
+            cfg: cfg.clone(),
+            base,
+            limit,
+            delimiter: b'\n',
+            current: None,
+            bytes: 0,
+            records: 0,
+            at_boundary: true,
+            paths: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Sets the byte marking the end of a record (default `b'\n'`)
+    pub fn delimiter(&mut self, delimiter: u8) -> &mut Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Paths of the output files created so far.  The last path is the file currently being
+    /// written to.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Finishes the current output file (see [`Writer::finish`]) and returns the paths of all
+    /// output files created.  If no data was written then no output files are created.  An
+    /// error from a write that was not yet reported is returned here.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.check_error()?;
+        self.close_part()?;
+        Ok(std::mem::take(&mut self.paths))
+    }
+
+    fn part_path(&self, ix: usize) -> PathBuf {
+        let mut name = OsString::new();
+        if let Some(stem) = self.base.file_stem() {
+            name.push(stem)
+        }
+        name.push(format!("-{:04}", ix));
+        if let Some(ext) = self.base.extension() {
+            name.push(".");
+            name.push(ext)
+        }
+        self.base.with_file_name(name)
+    }
+
+    fn open_part(&mut self) -> io::Result<()> {
+        if self.current.is_none() {
+            let path = self.part_path(self.paths.len());
+            let (wrt, path) = self.cfg.clone().path(&path).writer_with_path()?;
+            self.paths
+                .push(path.expect("Missing path for split writer part"));
+            self.current = Some(wrt);
+            self.bytes = 0;
+            self.records = 0;
+            self.at_boundary = true;
+        }
+        Ok(())
+    }
+
+    fn close_part(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(wrt) => wrt.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    // Return (and clear) an error from a previous write that was not reported because some of
+    // the data had already been accepted
+    fn check_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Write the start of buf to the current part (starting a new part if required), returning
+    // the number of bytes accepted
+    fn write_part(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.current.is_some() && self.at_boundary && self.limit_reached() {
+            self.close_part()?;
+        }
+        self.open_part()?;
+        let len = self.chunk_len(buf);
+        let n = self.current.as_mut().unwrap().write(&buf[..len])?;
+        if n == 0 {
+            return Err(Error::new(
+                ErrorKind::WriteZero,
+                "Split writer part accepted no data",
+            ));
+        }
+        let chunk = &buf[..n];
+        self.bytes += n as u64;
+        self.records += chunk.iter().filter(|c| **c == self.delimiter).count() as u64;
+        self.at_boundary = chunk.last() == Some(&self.delimiter);
+        Ok(n)
+    }
+
+    fn limit_reached(&self) -> bool {
+        match self.limit {
+            SplitLimit::Bytes(n) => self.bytes >= n,
+            SplitLimit::Records(n) => self.records >= n,
+        }
+    }
+
+    // Length of the prefix of buf ending after the n'th delimiter (if present)
+    fn records_end(&self, buf: &[u8], n: u64) -> Option<usize> {
+        buf.iter()
+            .enumerate()
+            .filter(|(_, c)| **c == self.delimiter)
+            .nth((n - 1) as usize)
+            .map(|(i, _)| i + 1)
+    }
+
+    // Amount of buf that can be written to the current part
+    fn chunk_len(&self, buf: &[u8]) -> usize {
+        if self.limit_reached() {
+            self.records_end(buf, 1).unwrap_or(buf.len())
+        } else {
+            match self.limit {
+                SplitLimit::Bytes(n) => buf
+                    .len()
+                    .min(usize::try_from(n - self.bytes).unwrap_or(usize::MAX)),
+                SplitLimit::Records(n) => {
+                    self.records_end(buf, n - self.records).unwrap_or(buf.len())
+                }
+            }
+        }
+    }
+}
+
+impl Write for SplitWriter {
+    // If an error occurs after some of buf has been written (i.e., when starting a new part) then
+    // the amount written is returned and the error is reported by the next call
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_error()?;
+        let mut written = 0;
+        while written < buf.len() {
+            match self.write_part(&buf[written..]) {
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) if written == 0 => return Err(e),
+                Err(e) => {
+                    self.error = Some(e);
+                    break;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_error()?;
+        match self.current.as_mut() {
+            Some(wrt) => wrt.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Byte counts, timings and resource usage for readers and writers
+//!
+//! [`Reader`] and [`Writer`] keep track of the number of (uncompressed) bytes passed through them,
+//! the number of compressed bytes read from or written to a file (where this is known), the
+//! elapsed time since they were created, and, once the compression utility has exited, the CPU
+//! time used by the child process.  The current values are available from [`Reader::stats`] and
+//! [`Writer::stats`], and the final values are returned by [`Reader::finish`] and
+//! [`Writer::finish`].
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::Write;
+//! use compress_io::compress::CompressIo;
+//!
+//! fn main() -> std::io::Result<()> {
+//!   let mut wrt = CompressIo::new().path("foo.xz").writer()?;
+//!   writeln!(wrt, "Hello world")?;
+//!   let stats = wrt.finish()?;
+//!   eprintln!("foo.xz: {}", stats);
+//!   Ok(())
+//! }
+//! ```
+//!
+//! [`Reader`]: crate::compress::Reader
+//! [`Writer`]: crate::compress::Writer
+//! [`Reader::stats`]: crate::compress::Reader::stats
+//! [`Writer::stats`]: crate::compress::Writer::stats
+//! [`Reader::finish`]: crate::compress::Reader::finish
+//! [`Writer::finish`]: crate::compress::Writer::finish
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Error, Seek},
+    os::unix::process::ExitStatusExt,
+    process::{Child, ExitStatus},
+    time::{Duration, Instant},
+};
+
+use crate::progress::{Progress, ProgressState};
+
+/// CPU time used by a child process
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuTime {
+    pub fn total(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+/// Statistics for a [`Reader`](crate::compress::Reader) or [`Writer`](crate::compress::Writer)
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct IoStats {
+    /// Uncompressed bytes read or written
+    pub bytes: u64,
+    /// Compressed bytes read from or written to a file.  `None` if the input or output is not a
+    /// file (i.e., stdin or stdout)
+    pub compressed_bytes: Option<u64>,
+    /// Time since the reader or writer was created (or until it was finished)
+    pub elapsed: Duration,
+    /// CPU time used by the compression utility.  Only available after the child process has
+    /// been waited on, and `None` if no child process was used
+    pub child_cpu: Option<CpuTime>,
+}
+
+impl IoStats {
+    /// Ratio of compressed to uncompressed bytes
+    pub fn ratio(&self) -> Option<f64> {
+        match (self.compressed_bytes, self.bytes) {
+            (Some(c), b) if b > 0 => Some(c as f64 / b as f64),
+            _ => None,
+        }
+    }
+
+    /// Uncompressed bytes per second
+    pub fn throughput(&self) -> f64 {
+        let t = self.elapsed.as_secs_f64();
+        if t > 0.0 {
+            self.bytes as f64 / t
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for IoStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", self.bytes)?;
+        if let Some(c) = self.compressed_bytes {
+            write!(f, " ({} compressed", c)?;
+            if let Some(r) = self.ratio() {
+                write!(f, ", ratio {:.3}", r)?
+            }
+            write!(f, ")")?
+        }
+        write!(
+            f,
+            " in {:.3}s ({:.1} MB/s)",
+            self.elapsed.as_secs_f64(),
+            self.throughput() / 1.0e6
+        )?;
+        if let Some(cpu) = self.child_cpu {
+            write!(
+                f,
+                ", child CPU {:.3}s user {:.3}s system",
+                cpu.user.as_secs_f64(),
+                cpu.system.as_secs_f64()
+            )?
+        }
+        Ok(())
+    }
+}
+
+// Current offset of a file (shared with any process using the same open file)
+fn file_offset(file: &File) -> Option<u64> {
+    let mut f = file;
+    f.stream_position().ok()
+}
+
+#[derive(Debug)]
+pub(crate) struct Tracker {
+    start: Instant,
+    end: Option<Instant>,
+    bytes: u64,
+    file: Option<File>,
+    cpu: Option<CpuTime>,
+    progress: Option<ProgressState>,
+}
+
+impl Tracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            end: None,
+            bytes: 0,
+            file: None,
+            cpu: None,
+            progress: None,
+        }
+    }
+
+    /// Sets the file being read from or written to (possibly by the child process), used to
+    /// find the number of compressed bytes
+    pub(crate) fn set_file(&mut self, file: File) {
+        self.file = Some(file)
+    }
+
+    pub(crate) fn set_progress(&mut self, progress: ProgressState) {
+        self.progress = Some(progress)
+    }
+
+    pub(crate) fn add(&mut self, n: usize) {
+        self.bytes += n as u64;
+        if self.progress.as_ref().map(|p| p.due()).unwrap_or(false) {
+            self.report(false)
+        }
+    }
+
+    pub(crate) fn finish(&mut self, cpu: Option<CpuTime>) {
+        self.end = Some(Instant::now());
+        if cpu.is_some() {
+            self.cpu = cpu
+        }
+        self.report(true)
+    }
+
+    fn compressed_bytes(&self) -> Option<u64> {
+        self.file.as_ref().and_then(file_offset)
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.end.unwrap_or_else(Instant::now) - self.start
+    }
+
+    fn report(&mut self, finished: bool) {
+        if let Some(total) = self.progress.as_ref().map(|p| p.total()) {
+            let p = Progress {
+                bytes: self.bytes,
+                compressed_bytes: self.compressed_bytes(),
+                compressed_total: total,
+                elapsed: self.elapsed(),
+                finished,
+            };
+            self.progress.as_mut().unwrap().report(&p)
+        }
+    }
+
+    pub(crate) fn stats(&self) -> IoStats {
+        IoStats {
+            bytes: self.bytes,
+            compressed_bytes: self.compressed_bytes(),
+            elapsed: self.elapsed(),
+            child_cpu: self.cpu,
+        }
+    }
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// Waits for a child process to exit using `wait4(2)`, returning the exit status and the CPU
+/// time used by the child.  The child process is reaped, so no further operations should be
+/// performed on it (which is why `child` is taken by value).
+pub(crate) fn wait_child(child: Child) -> io::Result<(ExitStatus, CpuTime)> {
+    let start = crate::process::deregister(&child);
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut ru) } >= 0 {
+            break;
+        }
+        let e = Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    let cpu = CpuTime {
+        user: timeval_to_duration(ru.ru_utime),
+        system: timeval_to_duration(ru.ru_stime),
+    };
+    let status = ExitStatus::from_raw(status);
+    crate::process::log_exit(child.id(), status, start);
+    Ok((status, cpu))
+}
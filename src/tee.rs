@@ -0,0 +1,229 @@
+//! Writing a single stream to several (possibly compressed) outputs
+//!
+//! A [`TeeWriter`] is built from several [`CompressIo`] configurations, each of which can have
+//! its own path, compression type, threads and level.  Everything written to the [`TeeWriter`] is
+//! written to every output.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::io::Write;
+//! use compress_io::{compress::CompressIo, tee::TeeWriter};
+//!
+//! fn main() -> std::io::Result<()> {
+//!   let mut wrt = TeeWriter::new(&[
+//!     CompressIo::new().path("customer.gz").level(6).clone(),
+//!     CompressIo::new().path("archive.zst").level(19).clone(),
+//!   ])?;
+//!   writeln!(wrt, "Hello world")?;
+//!   let report = wrt.finish();
+//!   for res in report.outputs() {
+//!     eprintln!("{:?}: {:?}", res.path, res.result);
+//!   }
+//!   report.check()
+//! }
+//! ```
+
+use std::{
+    fmt, fs,
+    io::{self, Error, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    compress::{CompressIo, Writer},
+    stats::IoStats,
+};
+
+#[derive(Debug)]
+struct TeeOutput {
+    path: Option<PathBuf>,
+    writer: Option<Writer>,
+    error: Option<io::Error>,
+}
+
+impl TeeOutput {
+    // Record the first error for this output and stop writing to it
+    fn fail(&mut self, e: io::Error) {
+        self.writer = None;
+        if self.error.is_none() {
+            self.error = Some(e)
+        }
+    }
+}
+
+/// A writer that sends the same data to several [`Writer`]s.
+///
+/// If writing to an output fails, the error is recorded and that output is no longer written
+/// to, but the remaining outputs are unaffected.  Write operations only return an error once
+/// all outputs have failed.  The outcome for each output is given by [`TeeWriter::finish`].
+#[derive(Debug)]
+pub struct TeeWriter {
+    outputs: Vec<TeeOutput>,
+}
+
+impl TeeWriter {
+    /// Creates a [`TeeWriter`] with one output for each [`CompressIo`] configuration.
+    /// Returns an error if no outputs are given or if any of the outputs can not be opened, in
+    /// which case any outputs already created are removed.
+    pub fn new<'a, I>(outputs: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = &'a CompressIo>,
+    {
+        let mut opened = Vec::new();
+        for cio in outputs {
+            match cio.writer_with_path() {
+                Ok((w, path)) => opened.push(TeeOutput {
+                    path,
+                    writer: Some(w),
+                    error: None,
+                }),
+                Err(e) => {
+                    discard(opened);
+                    return Err(e);
+                }
+            }
+        }
+        let outputs = opened;
+        if outputs.is_empty() {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No outputs specified for tee writer",
+            ))
+        } else {
+            Ok(Self { outputs })
+        }
+    }
+
+    /// Returns the number of outputs that have not failed
+    pub fn active(&self) -> usize {
+        self.outputs.iter().filter(|o| o.writer.is_some()).count()
+    }
+
+    /// Finishes all outputs (see [`Writer::finish`]), returning the outcome (including the
+    /// statistics for successful outputs) for each output in the order they were given to
+    /// [`TeeWriter::new`]
+    pub fn finish(mut self) -> TeeReport {
+        let outputs = self
+            .outputs
+            .drain(..)
+            .map(|mut o| {
+                let result = match (o.error.take(), o.writer.take()) {
+                    (Some(e), _) => Err(e),
+                    (None, w) => w.expect("Missing writer for tee output").finish(),
+                };
+                TeeResult {
+                    path: o.path,
+                    result,
+                }
+            })
+            .collect();
+        TeeReport { outputs }
+    }
+
+    fn check_active(&self) -> io::Result<()> {
+        if self.active() == 0 {
+            Err(Error::other("All outputs of tee writer have failed"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_active()?;
+        for o in self.outputs.iter_mut() {
+            if let Some(Err(e)) = o.writer.as_mut().map(|w| w.write_all(buf)) {
+                o.fail(e)
+            }
+        }
+        self.check_active().map(|_| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for o in self.outputs.iter_mut() {
+            if let Some(Err(e)) = o.writer.as_mut().map(|w| w.flush()) {
+                o.fail(e)
+            }
+        }
+        self.check_active()
+    }
+}
+
+// Finish the outputs opened before a later output failed to open (so their utilities are
+// reaped) and remove the files created
+fn discard(outputs: Vec<TeeOutput>) {
+    for o in outputs {
+        if let Some(w) = o.writer {
+            let _ = w.finish();
+        }
+        if let Some(p) = o.path {
+            let _ = fs::remove_file(p);
+        }
+    }
+}
+
+/// Outcome for a single output of a [`TeeWriter`]
+#[derive(Debug)]
+pub struct TeeResult {
+    /// Output path (after any compression suffix has been added), or `None` for stdout
+    pub path: Option<PathBuf>,
+    /// Statistics for the output if it was written successfully
+    pub result: io::Result<IoStats>,
+}
+
+impl fmt::Display for TeeResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(p) => write!(f, "{}", p.display())?,
+            None => write!(f, "<stdout>")?,
+        }
+        match &self.result {
+            Ok(s) => write!(f, ": OK, {}", s),
+            Err(e) => write!(f, ": {}", e),
+        }
+    }
+}
+
+/// Outcomes for all outputs of a [`TeeWriter`], returned by [`TeeWriter::finish`]
+#[derive(Debug)]
+pub struct TeeReport {
+    outputs: Vec<TeeResult>,
+}
+
+impl TeeReport {
+    pub fn outputs(&self) -> &[TeeResult] {
+        &self.outputs
+    }
+
+    pub fn into_outputs(self) -> Vec<TeeResult> {
+        self.outputs
+    }
+
+    /// Returns true if all outputs were written successfully
+    pub fn success(&self) -> bool {
+        self.outputs.iter().all(|o| o.result.is_ok())
+    }
+
+    /// Returns the error from the first output that failed, if any
+    pub fn check(self) -> io::Result<()> {
+        match self.outputs.into_iter().find(|o| o.result.is_err()) {
+            Some(o) => {
+                let msg = o.to_string();
+                let e = o.result.unwrap_err();
+                Err(Error::new(e.kind(), msg))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for TeeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for o in self.outputs.iter() {
+            writeln!(f, "{}", o)?
+        }
+        Ok(())
+    }
+}
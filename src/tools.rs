@@ -1,12 +1,26 @@
 use std::{
 	fmt,
+	io::{self, Error, ErrorKind, Read},
 	collections::HashMap,
 	cmp::Ordering,
 	path::{PathBuf, Path},
+	process::{Command, Stdio},
+	str::FromStr,
+	sync::{Arc, Mutex, OnceLock, RwLock},
+	thread,
+	time::{Duration, Instant},
 };
 
 use crate::compress_type::{CompressType, CompressThreads};
-use crate::path_utils::find_exec_path;
+use crate::path_utils::{find_exec_path, find_exec_path_in, is_executable};
+use crate::config;
+use crate::hardened;
+
+mod info;
+mod self_test;
+
+pub use info::{Candidate, CandidateStatus, FormatSelection, RegistryInfo, ToolInfo};
+pub use self_test::{SelfTestReport, ServiceMode, ServiceTest, TestOutcome};
 
 #[derive(Debug)]
 pub struct ToolKey {
@@ -52,14 +66,57 @@ pub struct ToolRegister {
 	decompress_tools: HashMap<CompressType, Vec<ToolKey>>,
 	compress_tools: HashMap<CompressType, Vec<ToolKey>>,
 	tools: Vec<Tool>,
+	warnings: Vec<String>,
+	search_path: Option<Vec<PathBuf>>,
 }
 
 impl ToolRegister {
 	pub fn new() -> Self { Self::default() }
 
-	fn add(mut self, mut tool: Tool) -> Self {
-		tool.add_path();
-		let ix = self.tools.len();
+	/// Directories searched for tools, or `None` if `$PATH` is used
+	pub fn search_path(&self) -> Option<&[PathBuf]> {
+		self.search_path.as_deref()
+	}
+	
+	/// Search for tools in `dirs` (in the order given) rather than in `$PATH`, and resolve the
+	/// paths of all registered tools again.  Tools given by absolute paths are not affected.
+	pub fn set_search_path<I, P>(&mut self, dirs: I)
+	where
+		I: IntoIterator<Item = P>,
+		P: AsRef<Path>,
+	{
+		self.search_path = Some(dirs.into_iter().map(|p| p.as_ref().to_owned()).collect());
+		self.rescan()
+	}
+	
+	/// Resolve the paths of all registered tools again, so that tools installed (or removed)
+	/// since the tools were registered, or changes to `$PATH`, are taken into account.
+	pub fn rescan(&mut self) {
+		for tool in self.tools.iter_mut() {
+			tool.add_path_in(self.search_path.as_deref())
+		}
+	}
+
+	/// Add a tool to the register after checking that the option templates of its
+	/// services are valid.  If a tool with the same name is already registered then it is
+	/// replaced.
+	pub fn register(&mut self, mut tool: Tool) -> io::Result<()> {
+		tool.validate()?;
+		tool.add_path_in(self.search_path.as_deref());
+		let ix = match self.tool_index(tool.name()) {
+			Some(ix) => {
+				for v in self.compress_tools.values_mut().chain(self.decompress_tools.values_mut()) {
+					v.retain(|tk| tk.ix != ix)
+				}
+				self.tools[ix] = tool;
+				ix
+			},
+			None => {
+				self.tools.push(tool);
+				self.tools.len() - 1
+			},
+		};
+		let tool = &self.tools[ix];
 		for service in tool.decompress_services() {
 			let v = self.decompress_tools.entry(service.compress_type).or_default();
 			v.push(ToolKey::new(ix, service.priority));
@@ -70,36 +127,108 @@ impl ToolRegister {
 			v.push(ToolKey::new(ix, service.priority));
 			v.sort_unstable()
 		}
-		self.tools.push(tool);
+		Ok(())
+	}
+	
+	/// Set the program to be run for an already registered tool (see [`Tool::program`])
+	pub fn set_program<P: AsRef<Path>>(&mut self, name: &str, program: P) -> io::Result<()> {
+		match self.tool_index(name) {
+			Some(ix) => {
+				let tool = &mut self.tools[ix];
+				tool.program = Some(program.as_ref().to_owned());
+				tool.add_path_in(self.search_path.as_deref());
+				Ok(())
+			},
+			None => Err(Error::new(ErrorKind::NotFound, format!("Unknown tool {}", name))),
+		}
+	}
+	
+	/// Problems found when configuring the register (i.e., errors in configuration files)
+	pub fn warnings(&self) -> &[String] {
+		&self.warnings
+	}
+	
+	pub(crate) fn warn(&mut self, msg: String) {
+		self.warnings.push(msg)
+	}
+	
+	fn add(mut self, tool: Tool) -> Self {
+		self.register(tool).expect("Invalid built-in tool definition");
 		self
 	} 	
 	
-	fn get_tool(&self, vt: Option<&Vec<ToolKey>>) -> Option<&Tool> {
-		match vt {	
-			Some(v) => {	
-				let mut ix = None;
-				for tk in v.iter() {
-					if self.tools[tk.ix].path().is_some() {
-						ix = Some(tk.ix);
-						break;
-					}
-				}; 
-				ix.map(|x| &self.tools[x])
-			},
-			None => None,
-		}	
+	fn tool_index(&self, name: &str) -> Option<usize> {
+		self.tools.iter().position(|t| t.name() == name)
+	}
+	
+	/// All registered tools
+	pub fn tools(&self) -> &[Tool] {
+		&self.tools
+	}
+	
+	/// Returns true if a tool called `name` is registered
+	pub fn contains(&self, name: &str) -> bool {
+		self.tool_index(name).is_some()
+	}
+	
+	/// Give preference to the listed tools (in the order given) for both compression and
+	/// decompression of `ct`.  Tools not listed keep their relative order after the listed
+	/// tools.  Unknown tool names are ignored.
+	pub fn prefer<S: AsRef<str>>(&mut self, ct: CompressType, names: &[S]) {
+		for (i, name) in names.iter().enumerate() {
+			if let Some(ix) = self.tool_index(name.as_ref()) {
+				let priority = usize::MAX - i;
+				for v in [self.compress_tools.get_mut(&ct), self.decompress_tools.get_mut(&ct)].into_iter().flatten() {
+					v.iter_mut().filter(|tk| tk.ix == ix).for_each(|tk| tk.priority = priority);
+					v.sort()
+				}
+			}
+		}
+	}
+	
+	/// Prevent a tool from being selected, either for all compression types (if `ct` is `None`)
+	/// or only for `ct`.  Unknown tool names are ignored.
+	pub fn disable(&mut self, name: &str, ct: Option<CompressType>) {
+		if let Some(ix) = self.tool_index(name) {
+			for (c, v) in self.compress_tools.iter_mut().chain(self.decompress_tools.iter_mut()) {
+				if ct.map(|x| x == *c).unwrap_or(true) {
+					v.retain(|tk| tk.ix != ix)
+				}
+			}
+		}
+	}
+	
+	// Select the first available tool that supports the required service
+	fn get_tool<F>(&self, vt: Option<&Vec<ToolKey>>, f: F) -> Option<&Tool> 
+	where
+		F: Fn(&Tool) -> Option<&Service>,
+	{
+		vt.and_then(|v| v.iter()
+			.map(|tk| &self.tools[tk.ix])
+			.find(|t| t.path().is_some() && f(t).map(|s| t.supports(s)).unwrap_or(false)))
 	}
 	
 	pub fn get_compress_tool(&self, ct: CompressType) -> Option<&Tool> {
-		self.get_tool(self.compress_tools.get(&ct))
+		let tool = self.get_tool(self.compress_tools.get(&ct), |t| t.get_compress(ct));
+		log_selection(tool, ct, "compression");
+		tool
 	}
 	
 	pub fn get_decompress_tool(&self, ct: CompressType) -> Option<&Tool> {
-		self.get_tool(self.decompress_tools.get(&ct))
+		let tool = self.get_tool(self.decompress_tools.get(&ct), |t| t.get_decompress(ct));
+		log_selection(tool, ct, "decompression");
+		tool
 	}	
 }
 
-#[derive(Default, Debug)]
+fn log_selection(tool: Option<&Tool>, ct: CompressType, op: &str) {
+	match tool {
+		Some(t) => debug!("Selected {} ({}) for {} {}", t.name(), t.path().map(|p| p.display().to_string()).unwrap_or_default(), ct, op),
+		None => debug!("No tool available for {} {}", ct, op),
+	}
+}
+
+#[derive(Default, Debug, Clone)]
 struct ToolMap {
 	decompress: Vec<Service>,
 	compress: Vec<Service>,
@@ -135,17 +264,52 @@ impl ToolMap {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tool {
 	name: Box<str>,
 	inner: ToolMap,
+	program: Option<PathBuf>,
 	path: Option<PathBuf>,
+	version_args: Box<str>,
+	help_args: Box<str>,
+	probe_timeout: Duration,
+	version: OnceLock<Option<ToolVersion>>,
+	help: OnceLock<Option<String>>,
 }
 
 impl Tool {
 
 	pub fn new<S: AsRef<str>>(name: S) -> Self { 
-		Self{ name: Box::from(name.as_ref()), inner: ToolMap::default(), path: None }
+		Self{ 
+			name: Box::from(name.as_ref()), inner: ToolMap::default(), program: None, path: None,
+			version_args: Box::from("--version"), help_args: Box::from("--help"),
+			probe_timeout: PROBE_TIMEOUT, version: OnceLock::new(), help: OnceLock::new(),
+		}
+	}
+	/// Sets the arguments (separated by whitespace) used to obtain the version of the tool.
+	/// The default is `--version`.
+	pub fn version_args<S: AsRef<str>>(mut self, args: S) -> Self {
+		self.version_args = Box::from(args.as_ref());
+		self
+	}
+	/// Sets the arguments (separated by whitespace) used to obtain the help text of the tool,
+	/// which is used to check capabilities (see [`Service::requires`]).  The default is `--help`.
+	pub fn help_args<S: AsRef<str>>(mut self, args: S) -> Self {
+		self.help_args = Box::from(args.as_ref());
+		self
+	}
+	/// Sets how long the tool may run when obtaining its version or help text before it is
+	/// killed and treated as unavailable for services with requirements.  The default is 5
+	/// seconds.
+	pub fn probe_timeout(mut self, timeout: Duration) -> Self {
+		self.probe_timeout = timeout;
+		self
+	}
+	/// Sets the program to be run for this tool (by default the tool name).  If `program` is
+	/// an absolute path then `$PATH` will not be searched.
+	pub fn program<P: AsRef<Path>>(mut self, program: P) -> Self {
+		self.program = Some(program.as_ref().to_owned());
+		self
 	}
 	pub fn decompress(mut self, service: Service) -> Self {
 		self.inner.add_decompress(service);
@@ -171,138 +335,627 @@ impl Tool {
 	pub fn compress_services(&self) -> &[Service] {
 		self.inner.compress_services()
 	}
+	/// Check the option templates of all services
+	pub fn validate(&self) -> io::Result<()> {
+		for s in self.decompress_services().iter().chain(self.compress_services()) {
+			s.validate().map_err(|e| Error::new(e.kind(), format!("{}: {}", self.name, e)))?
+		}
+		Ok(())
+	}
 	pub fn add_path(&mut self) {
-		self.path = find_exec_path(self.name.as_ref())	
+		self.add_path_in(None)
+	}
+	/// As [`Tool::add_path`] but searching the directories in `search_path` (if given) rather
+	/// than `$PATH`
+	pub fn add_path_in(&mut self, search_path: Option<&[PathBuf]>) {
+		// Any previous probe results refer to the old path
+		self.version = OnceLock::new();
+		self.help = OnceLock::new();
+		let prog: &Path = match self.program.as_ref() {
+			Some(p) if p.is_absolute() => {
+				self.path = Some(p.clone()).filter(|p| is_executable(p) && hardened::check_program(p).is_ok());
+				return
+			},
+			Some(p) => p,
+			None => Path::new(self.name.as_ref()),
+		};
+		self.path = match search_path {
+			Some(dirs) => find_exec_path_in(prog, dirs),
+			None => find_exec_path(prog),
+		}
 	}
 	
 	pub fn path(&self) -> Option<&Path> {
 		self.path.as_deref()
 	}
+	
+	/// Version of the tool.  This is determined by running the tool the first time it is
+	/// required, and is `None` if the tool is not available or the version could not be
+	/// determined.
+	pub fn version(&self) -> Option<&ToolVersion> {
+		self.version.get_or_init(|| self.probe(&self.version_args)
+			.and_then(|s| ToolVersion::from_text(&s))).as_ref()
+	}
+	
+	/// Returns true if the tool's help text lists `capability` as an option, i.e. it appears
+	/// as a whole word at the start of a line, or after another form of the same option
+	/// (`-T#, --threads=#`).  The help text is obtained by running the tool the first time
+	/// it is required.
+	pub fn has_capability(&self, capability: &str) -> bool {
+		self.help.get_or_init(|| self.probe(&self.help_args)).as_deref()
+			.map(|s| lists_option(s, capability)).unwrap_or(false)
+	}
+	
+	/// Returns true if the tool meets the version and capability requirements of `service`.
+	/// The tool is only run if the service has requirements.
+	pub fn supports(&self, service: &Service) -> bool {
+		let version_ok = match service.min_version.as_ref() {
+			Some(v) => self.version().map(|x| x >= v).unwrap_or(false),
+			None => true,
+		};
+		version_ok && service.requires.iter().all(|c| self.has_capability(c))
+	}
+	
+	/// Threading options to be used with `service`.  If the tool version is too old to
+	/// support the thread option of the service (see [`Service::thread_min_version`]) then
+	/// [`CompressThreads::Default`] is returned.
+	pub fn threads(&self, service: &Service, threads: CompressThreads) -> CompressThreads {
+		match (threads, service.thread_min_version.as_ref()) {
+			(CompressThreads::Default, _) | (_, None) => threads,
+			(_, Some(v)) => if self.version().map(|x| x >= v).unwrap_or(false) { threads } else { CompressThreads::Default },
+		}
+	}
+	
+	// Run the tool and collect its output (stdout followed by stderr).  The tool is killed if
+	// it does not finish within the probe timeout, in which case None is returned.
+	fn probe(&self, args: &str) -> Option<String> {
+		let mut cmd = Command::new(self.path()?);
+		hardened::apply(&mut cmd).ok()?;
+		let mut child = cmd
+			.args(args.split_whitespace())
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.ok()?;
+		let stdout = read_in_thread(child.stdout.take());
+		let stderr = read_in_thread(child.stderr.take());
+		let deadline = Instant::now() + self.probe_timeout;
+		loop {
+			match child.try_wait() {
+				Ok(Some(_)) => break,
+				Ok(None) if Instant::now() < deadline => thread::sleep(PROBE_POLL_INTERVAL),
+				_ => {
+					// The output threads are left to finish when the pipes are closed
+					let _ = child.kill();
+					let _ = child.wait();
+					return None
+				},
+			}
+		}
+		let mut s = stdout.join().ok()?;
+		s.push_str(&stderr.join().ok()?);
+		Some(s)
+	}
 }
 
-#[derive(Debug)]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn read_in_thread<R: Read + Send + 'static>(rd: Option<R>) -> thread::JoinHandle<String> {
+	thread::spawn(move || {
+		let mut buf = Vec::new();
+		if let Some(mut r) = rd {
+			let _ = r.read_to_end(&mut buf);
+		}
+		String::from_utf8_lossy(&buf).into_owned()
+	})
+}
+
+// Checks whether `opt` is listed in the help text `help`: at the start of a line (ignoring
+// indentation), or after other forms of the option separated by ", ", and followed by
+// something other than a word character (so `--format` does not match `--formats`)
+fn lists_option(help: &str, opt: &str) -> bool {
+	let word_end = |r: &str| r.chars().next().map(|c| !(c.is_alphanumeric() || c == '-' || c == '_')).unwrap_or(true);
+	help.lines().any(|line| {
+		let mut s = line.trim_start();
+		loop {
+			if s.strip_prefix(opt).map(word_end).unwrap_or(false) {
+				return true
+			}
+			match s.find(", ") {
+				Some(i) if s.starts_with('-') && !s[..i].contains(char::is_whitespace) => s = &s[i + 2..],
+				_ => return false,
+			}
+		}
+	})
+}
+
+/// Version of a tool, i.e. `1.5.7`.  Missing trailing components are treated as zero when
+/// comparing versions, so `5.2` == `5.2.0`.
+#[derive(Debug, Clone)]
+pub struct ToolVersion(Vec<u32>);
+
+impl ToolVersion {
+	pub fn new(v: &[u32]) -> Self {
+		Self(v.to_vec())
+	}
+	
+	/// Extract a version from the output of a tool (i.e., `xz (XZ Utils) 5.2.5`).  The first
+	/// word that looks like a version (digits separated by at least one `.` and optionally
+	/// preceded by `v`) is used.
+	pub fn from_text(s: &str) -> Option<Self> {
+		s.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+			.map(|w| w.strip_prefix('v').unwrap_or(w).trim_end_matches('.'))
+			.filter(|w| w.contains('.'))
+			.find_map(|w| w.parse().ok())
+	}
+	
+	pub fn components(&self) -> &[u32] {
+		&self.0
+	}
+}
+
+impl FromStr for ToolVersion {
+	type Err = &'static str;
+	
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let v: Result<Vec<u32>, _> = s.split('.').map(|x| x.parse::<u32>()).collect();
+		match v {
+			Ok(v) if !v.is_empty() => Ok(Self(v)),
+			_ => Err("Illegal version"),
+		}
+	}
+}
+
+impl fmt::Display for ToolVersion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let v: Vec<_> = self.0.iter().map(|x| x.to_string()).collect();
+		write!(f, "{}", v.join("."))
+	}
+}
+
+impl Ord for ToolVersion {
+	fn cmp(&self, other: &Self) -> Ordering {
+		let n = self.0.len().max(other.0.len());
+		let get = |v: &[u32], i: usize| v.get(i).copied().unwrap_or(0);
+		(0..n).map(|i| get(&self.0, i).cmp(&get(&other.0, i)))
+			.find(|o| *o != Ordering::Equal)
+			.unwrap_or(Ordering::Equal)
+	}
+}
+
+impl PartialOrd for ToolVersion {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for ToolVersion {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for ToolVersion { }
+
+#[derive(Debug, Clone)]
 pub struct Service {
 	compress_type: CompressType,
 	options: Vec<ToolOpt>,
 	thread_option: Option<ToolOpt>,
+	all_cores_option: Option<ToolOpt>,
+	level_option: Option<ToolOpt>,
 	priority: usize,
+	min_version: Option<ToolVersion>,
+	thread_min_version: Option<ToolVersion>,
+	requires: Vec<Box<str>>,
 }
 
 impl Service {
 	pub fn new(compress_type: CompressType) -> Self {
-		Self{compress_type, options: Vec::new(), thread_option: None, priority: 0}
+		Self{
+			compress_type, options: Vec::new(), thread_option: None, all_cores_option: None, level_option: None, priority: 0,
+			min_version: None, thread_min_version: None, requires: Vec::new(),
+		}
 	}
 	
+	/// Adds a fixed option (which must not contain the `{n}` placeholder)
 	pub fn option(mut self, opt: ToolOpt) -> Self {
 		self.options.push(opt);
 		self
 	}
 	
+	/// Sets the option used to request a number of threads.  The template must contain the
+	/// `{n}` placeholder, which will be replaced by the number of threads
 	pub fn thread_option(mut self, opt: ToolOpt) -> Self {
 		self.thread_option = Some(opt);
 		self
 	}
 	
+	/// Sets the option used when all available cores are requested with
+	/// [`CompressThreads::NCores`] (i.e., `-T0` for [zstd] or [xz]).  If not set then the
+	/// thread option is used with the number of cores.
+	///
+	/// [zstd]: https://facebook.github.io/zstd/
+	/// [xz]: https://tukaani.org/xz/
+	pub fn all_cores_option(mut self, opt: ToolOpt) -> Self {
+		self.all_cores_option = Some(opt);
+		self
+	}
+	
+	/// Sets the option used to set the compression level.  The template must contain the
+	/// `{n}` placeholder, which will be replaced by the level
+	pub fn level_option(mut self, opt: ToolOpt) -> Self {
+		self.level_option = Some(opt);
+		self
+	}
+	
 	pub fn priority(mut self, priority: usize) -> Self {
 		self.priority = priority;
 		self
 	}
+	
+	/// Sets the minimum tool version required for this service.  If the installed tool is
+	/// older (or its version can not be determined) then the service will not be used.
+	pub fn min_version(mut self, version: ToolVersion) -> Self {
+		self.min_version = Some(version);
+		self
+	}
+	
+	/// Sets the minimum tool version required for the thread options.  If the installed tool
+	/// is older then the service is used without thread options.
+	pub fn thread_min_version(mut self, version: ToolVersion) -> Self {
+		self.thread_min_version = Some(version);
+		self
+	}
+	
+	/// Adds a capability required by this service: a string that must appear in the
+	/// help text of the tool (see [`Tool::help_args`]), i.e. `--format=xz`.
+	pub fn requires<S: AsRef<str>>(mut self, capability: S) -> Self {
+		self.requires.push(Box::from(capability.as_ref()));
+		self
+	}
+
 	pub fn compress_type(&self) -> CompressType { self.compress_type }	
+	
+	/// Check that all option templates are valid
+	pub fn validate(&self) -> io::Result<()> {
+		for o in self.options.iter() {
+			o.validate(false)?
+		}
+		if let Some(o) = self.thread_option.as_ref() {
+			o.validate(true)?
+		}
+		if let Some(o) = self.all_cores_option.as_ref() {
+			o.validate(false)?
+		}
+		if let Some(o) = self.level_option.as_ref() {
+			o.validate(true)?
+		}
+		Ok(())
+	}
+	
+	// Options to be used for the requested threads, with the thread count for substitution
+	fn selected_options(&self, threads: CompressThreads, level: Option<u32>) -> impl Iterator<Item = (&ToolOpt, Option<usize>)> {
+		let thread_opt = match (threads, self.all_cores_option.as_ref()) {
+			(CompressThreads::NCores, Some(o)) => Some((o, None)),
+			_ => self.thread_option.as_ref().and_then(|o| threads.n_threads().map(|n| (o, Some(n)))),
+		};
+		let level_opt = self.level_option.as_ref().and_then(|o| level.map(|l| (o, Some(l as usize))));
+		self.options.iter().map(|o| (o, None)).chain(level_opt).chain(thread_opt)
+	}
+	
+	/// Command line arguments for the utility
 	pub fn args(&self, threads: CompressThreads) -> Vec<String> {
-		let mut v: Vec<_> = self.options.iter().map(|o| format!("{}", o)).collect();
-		if let (Some(o), Some(n)) = (self.thread_option.as_ref(), threads.n_threads()) {
-			match o {
-				ToolOpt::Short(s) => v.push(format!("-{}{}", s, n)),
-				ToolOpt::Long(s) => {
-					v.push(format!("--{}", s));
-					v.push(format!("{}", n))
-				}
-			}	
+		self.args_with_level(threads, None)
+	}
+	
+	/// Command line arguments for the utility, including the compression level if given
+	/// and the service has a level option
+	pub fn args_with_level(&self, threads: CompressThreads, level: Option<u32>) -> Vec<String> {
+		let mut v = Vec::new();
+		for (o, n) in self.selected_options(threads, level) {
+			if let ToolOpt::Args(s) = o {
+				v.extend(s.split_whitespace().map(|a| render(a, n)))
+			}
 		}
 		v
 	}
+	
+	/// Environment variables to be set for the utility
+	pub fn envs(&self, threads: CompressThreads) -> Vec<(String, String)> {
+		self.envs_with_level(threads, None)
+	}
+	
+	/// Environment variables to be set for the utility, including the compression level if
+	/// given and the service has a level option
+	pub fn envs_with_level(&self, threads: CompressThreads, level: Option<u32>) -> Vec<(String, String)> {
+		self.selected_options(threads, level).filter_map(|(o, n)| match o {
+			ToolOpt::Env(k, s) => Some((k.to_string(), render(s, n))),
+			_ => None,
+		}).collect()
+	}
 }
 
+/// An option template for a compression utility.
+///
+/// Templates can contain the placeholder `{n}`, which is replaced by the number of threads
+/// when used as a thread option (see [`Service::thread_option`]) or by the compression level
+/// when used as a level option (see [`Service::level_option`]).  No other use of `{` or `}` is
+/// allowed.  Templates are checked when a tool is registered using [`ToolRegister::register`].
+///
+/// ```
+/// use compress_io::tools::ToolOpt;
+///
+/// // Command line options (whitespace separates arguments)
+/// let o1 = ToolOpt::args("-dcf");
+/// let o2 = ToolOpt::args("-p{n}");
+/// let o3 = ToolOpt::args("--threads={n}");
+/// let o4 = ToolOpt::args("-T {n}");
+/// // Environment variable for the child process
+/// let o5 = ToolOpt::env("XZ_OPT", "-T{n}");
+/// ```
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub enum ToolOpt {
-	Short(Box<str>),
-	Long(Box<str>),
+	/// One or more command line arguments separated by whitespace
+	Args(Box<str>),
+	/// An environment variable to be set for the child process
+	Env(Box<str>, Box<str>),
 }
 
 impl fmt::Display for ToolOpt {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
-			Self::Short(s) => write!(f, "-{}", s),
-			Self::Long(s) => write!(f, "--{}", s),
+			Self::Args(s) => write!(f, "{}", s),
+			Self::Env(k, s) => write!(f, "{}={}", k, s),
 		}
 	}	
 }
 
+const THREADS: &str = "{n}";
+
+fn render(s: &str, n: Option<usize>) -> String {
+	match n {
+		Some(n) => s.replace(THREADS, &format!("{}", n)),
+		None => s.to_owned(),
+	}
+}
+
+fn invalid_opt(opt: &ToolOpt, msg: &str) -> Error {
+	Error::new(ErrorKind::InvalidInput, format!("Invalid tool option '{}': {}", opt, msg))
+}
+
 impl ToolOpt {
-	pub fn short<S: AsRef<str>>(opt: S) -> Self { 
-		Self::Short(Box::from(opt.as_ref())) 
+	pub fn args<S: AsRef<str>>(opt: S) -> Self { 
+		Self::Args(Box::from(opt.as_ref())) 
+	}
+	pub fn env<K: AsRef<str>, S: AsRef<str>>(key: K, val: S) -> Self { 
+		Self::Env(Box::from(key.as_ref()), Box::from(val.as_ref()))
+	}
+	
+	fn template(&self) -> &str {
+		match self {
+			Self::Args(s) | Self::Env(_, s) => s,
+		}
 	}
-	pub fn long<S: AsRef<str>>(opt: S) -> Self { 
-		Self::Long(Box::from(opt.as_ref()))
+	
+	/// Returns true if the template contains the thread placeholder `{n}`
+	pub fn has_threads(&self) -> bool {
+		self.template().contains(THREADS)
 	}
+	
+	/// Check that the option is well formed.  If `placeholder` is true (for thread and level
+	/// options) then the `{n}` placeholder is required, otherwise it is not allowed
+	pub fn validate(&self, placeholder: bool) -> io::Result<()> {
+		match self {
+			Self::Args(s) if s.trim().is_empty() => return Err(invalid_opt(self, "empty argument list")),
+			Self::Env(k, _) if k.is_empty() || k.contains(['=', '\0']) => return Err(invalid_opt(self, "illegal environment variable name")),
+			Self::Env(_, s) if s.contains('\0') => return Err(invalid_opt(self, "illegal environment variable value")),
+			_ => (),
+		}
+		if self.template().replace(THREADS, "").contains(['{', '}']) {
+			Err(invalid_opt(self, "only the {n} placeholder is allowed"))
+		} else if placeholder && !self.has_threads() {
+			Err(invalid_opt(self, "thread and level options require the {n} placeholder"))
+		} else if !placeholder && self.has_threads() {
+			Err(invalid_opt(self, "{n} placeholder is only allowed in thread and level options"))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// The tool that would be used to decompress `ct` files
+pub fn decompress_tool(ct: CompressType) -> Option<Tool> {
+	registry().get_decompress_tool(ct).cloned()
 }
 
+/// The tool that would be used to compress to `ct`
+pub fn compress_tool(ct: CompressType) -> Option<Tool> {
+	registry().get_compress_tool(ct).cloned()
+}
+
+/// The tool that would be used to decompress `ct` files.  The global register current at the
+/// time of the call is kept until the program exits, even if replaced by [`rescan`].
+#[deprecated(since = "0.7.0", note = "use `decompress_tool` or `registry`")]
 pub fn get_decompress_tool(ct: CompressType) -> Option<&'static Tool> {
-	TOOLS.get_decompress_tool(ct)
+	static_registry().get_decompress_tool(ct)
 }
 
+/// The tool that would be used to compress to `ct` (see [`get_decompress_tool`])
+#[deprecated(since = "0.7.0", note = "use `compress_tool` or `registry`")]
 pub fn get_compress_tool(ct: CompressType) -> Option<&'static Tool> {
-	TOOLS.get_compress_tool(ct)
+	static_registry().get_compress_tool(ct)
+}
+
+/// Search for the tools again, so that tools installed since the register was set up, or
+/// changes to `$PATH`, are taken into account.  The configuration files and environment
+/// variables are also read again.  Readers and writers already created are not affected.
+///
+/// The previous register is freed once it is no longer in use.  As the whole register is set
+/// up again, this should be called when the environment is known to have changed rather than
+/// before every use.
+///
+/// ```no_run
+/// // Pick up a newly installed pigz
+/// compress_io::tools::rescan();
+/// ```
+pub fn rescan() {
+	let reg = Arc::new(default_register(None));
+	*TOOLS.write().unwrap() = reg;
+	SEARCH_PATH_TOOLS.lock().unwrap().clear();
+}
+
+/// The global tool register.  The register returned stays valid while it is held, even if it
+/// is replaced by a later call to [`rescan`].
+///
+/// ```
+/// use compress_io::{compress_type::CompressType, tools};
+///
+/// let reg = tools::registry();
+/// if let Some(tool) = reg.get_decompress_tool(CompressType::Gzip) {
+///   println!("gzip files decompressed by {}", tool.name());
+/// }
+/// ```
+pub fn registry() -> Arc<ToolRegister> {
+	TOOLS.read().unwrap().clone()
+}
+
+// The tool register for a custom search path (see CompressIo::search_path()), or the global
+// register if `search_path` is `None`.  Registers are set up on first use and cached until the
+// next call to rescan(), after which they are freed once no longer in use.
+pub(crate) fn registry_for(search_path: Option<&[PathBuf]>) -> Arc<ToolRegister> {
+	match search_path {
+		Some(dirs) => SEARCH_PATH_TOOLS.lock().unwrap().entry(dirs.to_vec())
+			.or_insert_with(|| Arc::new(default_register(Some(dirs.to_vec()))))
+			.clone(),
+		None => registry(),
+	}
+}
+
+/// Test all services of the tools available in the user's `$PATH`, compressing and
+/// decompressing a sample with each service (see [`ToolRegister::self_test`]).  This runs
+/// each tool several times so is not fast, and is intended for health checks and diagnostics.
+///
+/// ```no_run
+/// let report = compress_io::tools::self_test();
+/// println!("{}", report);
+/// if !report.passed() {
+///   std::process::exit(1)
+/// }
+/// ```
+pub fn self_test() -> SelfTestReport {
+	registry().self_test()
+}
+
+/// Describe the available tools and which tool will be selected for compression and
+/// decompression of each format.  The result implements `Display`, giving a summary suitable
+/// for diagnostic output.
+///
+/// ```no_run
+/// use compress_io::{compress_type::CompressType, tools::registry_info};
+///
+/// let info = registry_info();
+/// println!("{}", info);
+/// let bzip2 = info.format(CompressType::Bzip2).and_then(|f| f.selected_compress());
+/// println!("bzip2 compression by {}", bzip2.unwrap_or("(none)"));
+/// ```
+pub fn registry_info() -> RegistryInfo {
+	registry().info()
+}
+
+/// Problems found when configuring the tool register from configuration files
+pub fn config_warnings() -> Vec<String> {
+	registry().warnings().to_vec()
+}
+
+/// Problems found when configuring the tool register (see [`get_decompress_tool`] for the
+/// lifetime of the register)
+#[deprecated(since = "0.7.0", note = "use `config_warnings` or `registry`")]
+pub fn warnings() -> &'static [String] {
+	static_registry().warnings()
+}
+
+// The global register for the deprecated functions returning references into it.  The register
+// is leaked (once for each call to rescan() that is followed by a call to this function).
+pub(crate) fn static_registry() -> &'static ToolRegister {
+	let reg = registry();
+	let mut leaked = LEAKED_TOOLS.lock().unwrap();
+	match *leaked {
+		Some(r) if std::ptr::eq(r, Arc::as_ptr(&reg)) => r,
+		_ => {
+			let r: &'static ToolRegister = Box::leak(Box::new(reg));
+			*leaked = Some(r);
+			r
+		},
+	}
+}
+
+// Sets up the register with the built-in tools followed by any changes from the configuration
+// files and environment, searching for tools in `search_path` (or `$PATH` if `None`)
+fn default_register(search_path: Option<Vec<PathBuf>>) -> ToolRegister {
+	let mut reg = ToolRegister { search_path, ..Default::default() }
+		.add(Tool::new("uncompress")
+			.decompress(Service::new(CompressType::Compress).priority(10)))
+		.add(Tool::new("gzip")
+			.decompress(Service::new(CompressType::Gzip).option(ToolOpt::args("-dcf")).priority(10))
+			.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::args("-dcf")).priority(5))
+			.decompress(Service::new(CompressType::Compress).option(ToolOpt::args("-dcf")).priority(5))
+			.compress(Service::new(CompressType::Gzip).level_option(ToolOpt::args("-{n}")).priority(5)))
+		.add(Tool::new("pigz")
+			.decompress(Service::new(CompressType::Gzip).option(ToolOpt::args("-dcf")))
+			.decompress(Service::new(CompressType::Compress).option(ToolOpt::args("-dcf")))
+			.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::args("-dcf")))
+			.compress(Service::new(CompressType::Gzip).thread_option(ToolOpt::args("--processes {n}")).level_option(ToolOpt::args("-{n}")).priority(10)))
+		.add(Tool::new("igzip")
+			.decompress(Service::new(CompressType::Gzip).option(ToolOpt::args("-dc")))
+			.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::args("-dc")))
+			.compress(Service::new(CompressType::Gzip).option(ToolOpt::args("-c")).thread_option(ToolOpt::args("-T {n}")).level_option(ToolOpt::args("-{n}"))))
+		.add(Tool::new("bgzip")
+			.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::args("-dcf")).priority(10))
+			.decompress(Service::new(CompressType::Gzip).option(ToolOpt::args("-dcf")))
+			.compress(Service::new(CompressType::Bgzip).thread_option(ToolOpt::args("--threads {n}")).level_option(ToolOpt::args("--compress-level {n}")).priority(10))
+			.compress(Service::new(CompressType::Gzip).level_option(ToolOpt::args("--compress-level {n}")))) // Compression with bgzip will give a Bgzip file, but this is compatible with gzip format so we can use this as a last resort
+		.add(Tool::new("bzip2")
+			.decompress(Service::new(CompressType::Bzip2).option(ToolOpt::args("-dcf")).priority(10))
+			.compress(Service::new(CompressType::Bzip2).level_option(ToolOpt::args("-{n}")).priority(5)))
+		.add(Tool::new("pbzip2")
+			.decompress(Service::new(CompressType::Bzip2).option(ToolOpt::args("-dcf")).priority(5))
+			.compress(Service::new(CompressType::Bzip2).thread_option(ToolOpt::args("-p{n}")).level_option(ToolOpt::args("-{n}")).priority(10)))	
+		.add(Tool::new("xz")
+			.decompress(Service::new(CompressType::Xz).option(ToolOpt::args("-dcf")).priority(10))
+			.decompress(Service::new(CompressType::Lzma).option(ToolOpt::args("-dcf")).priority(10))
+			.compress(Service::new(CompressType::Xz).thread_option(ToolOpt::args("--threads={n}")).all_cores_option(ToolOpt::args("-T0"))
+				.thread_min_version(ToolVersion::new(&[5, 2])).level_option(ToolOpt::args("-{n}")).priority(10))
+			.compress(Service::new(CompressType::Lzma).option(ToolOpt::args("--format=lzma")).thread_option(ToolOpt::args("--threads={n}")).all_cores_option(ToolOpt::args("-T0"))
+				.thread_min_version(ToolVersion::new(&[5, 2])).level_option(ToolOpt::args("-{n}")).priority(10)))
+		.add(Tool::new("lzma")
+			.decompress(Service::new(CompressType::Lzma).option(ToolOpt::args("-dcf")).priority(5))
+			.compress(Service::new(CompressType::Lzma).level_option(ToolOpt::args("-{n}")).priority(5)))
+		.add(Tool::new("lz4")
+			.decompress(Service::new(CompressType::Lz4).option(ToolOpt::args("-dcf")).priority(5))
+			.compress(Service::new(CompressType::Lz4).option(ToolOpt::args("-c")).level_option(ToolOpt::args("-{n}")).priority(5)))				
+		// Support for formats other than zstd depends on the libraries zstd was built with,
+		// which is shown in the long help
+		.add(Tool::new("zstd").help_args("-H")
+			.decompress(Service::new(CompressType::Zstd).option(ToolOpt::args("-dcf")).priority(10))
+			.decompress(Service::new(CompressType::Gzip).option(ToolOpt::args("-dcf")).requires("--format=gzip"))
+			.decompress(Service::new(CompressType::Xz).option(ToolOpt::args("-dcf")).requires("--format=xz"))
+			.decompress(Service::new(CompressType::Lzma).option(ToolOpt::args("-dcf")).requires("--format=lzma"))
+			.decompress(Service::new(CompressType::Lz4).option(ToolOpt::args("-dcf")).requires("--format=lz4"))
+			.compress(Service::new(CompressType::Zstd).thread_option(ToolOpt::args("-T{n}")).all_cores_option(ToolOpt::args("-T0")).level_option(ToolOpt::args("-{n}")).priority(10))
+			.compress(Service::new(CompressType::Gzip).option(ToolOpt::args("--format=gzip")).requires("--format=gzip").level_option(ToolOpt::args("-{n}")))
+			.compress(Service::new(CompressType::Xz).option(ToolOpt::args("--format=xz")).requires("--format=xz").level_option(ToolOpt::args("-{n}")))
+			.compress(Service::new(CompressType::Lzma).option(ToolOpt::args("--format=lzma")).requires("--format=lzma").level_option(ToolOpt::args("-{n}")))
+			.compress(Service::new(CompressType::Lz4).option(ToolOpt::args("--format=lz4")).requires("--format=lz4").level_option(ToolOpt::args("-{n}")))
+		);
+	config::configure(&mut reg);
+	reg
 }
 
 lazy_static! {
-	static ref TOOLS: ToolRegister = {
-		ToolRegister::default()
-			.add(Tool::new("uncompress")
-				.decompress(Service::new(CompressType::Compress).priority(10)))
-			.add(Tool::new("gzip")
-				.decompress(Service::new(CompressType::Gzip).option(ToolOpt::short("dcf")).priority(10))
-				.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::short("dcf")).priority(5))
-				.decompress(Service::new(CompressType::Compress).option(ToolOpt::short("dcf")).priority(5))
-				.compress(Service::new(CompressType::Gzip).priority(5)))
-			.add(Tool::new("pigz")
-				.decompress(Service::new(CompressType::Gzip).option(ToolOpt::short("dcf")))
-				.decompress(Service::new(CompressType::Compress).option(ToolOpt::short("dcf")))
-				.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::short("dcf")))
-				.compress(Service::new(CompressType::Gzip).thread_option(ToolOpt::long("processes")).priority(10)))
-			.add(Tool::new("bgzip")
-				.decompress(Service::new(CompressType::Bgzip).option(ToolOpt::short("dcf")).priority(10))
-				.decompress(Service::new(CompressType::Gzip).option(ToolOpt::short("dcf")))
-				.compress(Service::new(CompressType::Bgzip).thread_option(ToolOpt::long("threads")).priority(10))
-				.compress(Service::new(CompressType::Gzip))) // Compression with bgzip will give a Bgzip file, but this is compatible with gzip format so we can use this as a last resort
-			.add(Tool::new("bzip2")
-				.decompress(Service::new(CompressType::Bzip2).option(ToolOpt::short("dcf")).priority(10))
-				.compress(Service::new(CompressType::Bzip2).priority(5)))
-			.add(Tool::new("pbzip2")
-				.decompress(Service::new(CompressType::Bzip2).option(ToolOpt::short("dcf")).priority(5))
-				.compress(Service::new(CompressType::Bzip2).thread_option(ToolOpt::short("dcf")).priority(10)))	
-			.add(Tool::new("xz")
-				.decompress(Service::new(CompressType::Xz).option(ToolOpt::short("dcf")).priority(10))
-				.decompress(Service::new(CompressType::Lzma).option(ToolOpt::short("dcf")).priority(10))
-				.compress(Service::new(CompressType::Xz).thread_option(ToolOpt::long("threads")).priority(10))
-				.compress(Service::new(CompressType::Lzma).option(ToolOpt::long("format=lzma")).thread_option(ToolOpt::long("threads")).priority(10)))
-			.add(Tool::new("lzma")
-				.decompress(Service::new(CompressType::Lzma).option(ToolOpt::short("dcf")).priority(5))
-				.compress(Service::new(CompressType::Lzma).priority(5)))
-			.add(Tool::new("lz4")
-				.decompress(Service::new(CompressType::Lzma).option(ToolOpt::short("dcfm")).priority(5))
-				.compress(Service::new(CompressType::Lzma).priority(5)))				
-			.add(Tool::new("zstd")
-				.decompress(Service::new(CompressType::Zstd).option(ToolOpt::short("dcf")).priority(10))
-				.decompress(Service::new(CompressType::Gzip).option(ToolOpt::short("dcf")))
-				.decompress(Service::new(CompressType::Xz).option(ToolOpt::short("dcf")))
-				.decompress(Service::new(CompressType::Lzma).option(ToolOpt::short("dcf")))
-				.decompress(Service::new(CompressType::Lz4).option(ToolOpt::short("dcf")))
-				.compress(Service::new(CompressType::Zstd).thread_option(ToolOpt::short("T")).priority(10))
-				.compress(Service::new(CompressType::Gzip).option(ToolOpt::long("format=gzip")))
-				.compress(Service::new(CompressType::Xz).option(ToolOpt::long("format=xz")))
-				.compress(Service::new(CompressType::Lzma).option(ToolOpt::long("format=lzma")))
-				.compress(Service::new(CompressType::Lz4).option(ToolOpt::long("format=lz4")))
-			)
-	};
+	static ref TOOLS: RwLock<Arc<ToolRegister>> = RwLock::new(Arc::new(default_register(None)));
+	static ref SEARCH_PATH_TOOLS: Mutex<HashMap<Vec<PathBuf>, Arc<ToolRegister>>> = Mutex::new(HashMap::new());
+	static ref LEAKED_TOOLS: Mutex<Option<&'static ToolRegister>> = Mutex::new(None);
 }
@@ -0,0 +1,230 @@
+//! Introspection of a [`ToolRegister`]
+
+use std::{fmt, path::PathBuf};
+
+use super::{Service, Tool, ToolKey, ToolRegister, ToolVersion};
+use crate::compress_type::CompressType;
+
+/// Description of a registered tool
+#[derive(Debug, Clone)]
+pub struct ToolInfo {
+    pub name: String,
+    /// Resolved path of the tool, or `None` if the tool was not found
+    pub path: Option<PathBuf>,
+    pub version: Option<ToolVersion>,
+    pub compress: Vec<CompressType>,
+    pub decompress: Vec<CompressType>,
+}
+
+impl fmt::Display for ToolInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |v: &[CompressType]| {
+            v.iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        match self.path.as_ref() {
+            Some(p) => write!(f, "{:<10} {}", self.name, p.display())?,
+            None => write!(f, "{:<10} (not found)", self.name)?,
+        }
+        if let Some(v) = self.version.as_ref() {
+            write!(f, " version {}", v)?
+        }
+        write!(
+            f,
+            " compress: [{}] decompress: [{}]",
+            list(&self.compress),
+            list(&self.decompress)
+        )
+    }
+}
+
+/// Status of a tool as a candidate for a service
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CandidateStatus {
+    /// The tool will be used
+    Selected,
+    /// The tool is available but a candidate with a higher preference will be used
+    Available,
+    /// The tool was not found
+    NotFound,
+    /// The tool was found but does not meet the version or capability requirements
+    Unsupported,
+}
+
+impl fmt::Display for CandidateStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Selected => write!(f, "selected"),
+            Self::Available => write!(f, "available"),
+            Self::NotFound => write!(f, "not found"),
+            Self::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+/// A tool that can provide a service, with its priority and status
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub tool: String,
+    pub priority: usize,
+    pub status: CandidateStatus,
+}
+
+/// Candidate tools, in order of preference, for compression and decompression of a format
+#[derive(Debug, Clone)]
+pub struct FormatSelection {
+    pub compress_type: CompressType,
+    pub compress: Vec<Candidate>,
+    pub decompress: Vec<Candidate>,
+}
+
+fn selected(v: &[Candidate]) -> Option<&str> {
+    v.iter()
+        .find(|c| c.status == CandidateStatus::Selected)
+        .map(|c| c.tool.as_str())
+}
+
+impl FormatSelection {
+    /// Name of the tool that will be used for compression
+    pub fn selected_compress(&self) -> Option<&str> {
+        selected(&self.compress)
+    }
+
+    /// Name of the tool that will be used for decompression
+    pub fn selected_decompress(&self) -> Option<&str> {
+        selected(&self.decompress)
+    }
+}
+
+impl fmt::Display for FormatSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |v: &[Candidate]| {
+            if v.is_empty() {
+                "(none)".to_string()
+            } else {
+                v.iter()
+                    .map(|c| format!("{}({}, {})", c.tool, c.priority, c.status))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+        };
+        writeln!(
+            f,
+            "{:<10} compress:   {:<10} candidates: {}",
+            self.compress_type.to_string(),
+            self.selected_compress().unwrap_or("-"),
+            list(&self.compress)
+        )?;
+        write!(
+            f,
+            "{:<10} decompress: {:<10} candidates: {}",
+            "",
+            self.selected_decompress().unwrap_or("-"),
+            list(&self.decompress)
+        )
+    }
+}
+
+/// Description of the tools in a register and of the tools selected for each format,
+/// obtained from [`registry_info`](crate::tools::registry_info) or [`ToolRegister::info`]
+#[derive(Debug, Clone)]
+pub struct RegistryInfo {
+    pub tools: Vec<ToolInfo>,
+    pub formats: Vec<FormatSelection>,
+    pub warnings: Vec<String>,
+}
+
+impl RegistryInfo {
+    pub fn format(&self, ct: CompressType) -> Option<&FormatSelection> {
+        self.formats.iter().find(|f| f.compress_type == ct)
+    }
+}
+
+impl fmt::Display for RegistryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "compress_io {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "Tools:")?;
+        for t in self.tools.iter() {
+            writeln!(f, "  {}", t)?
+        }
+        writeln!(f, "Formats:")?;
+        for s in self.formats.iter() {
+            for l in s.to_string().lines() {
+                writeln!(f, "  {}", l)?
+            }
+        }
+        for w in self.warnings.iter() {
+            writeln!(f, "Warning: {}", w)?
+        }
+        Ok(())
+    }
+}
+
+impl ToolRegister {
+    fn candidates<F>(&self, vt: Option<&Vec<ToolKey>>, f: F) -> Vec<Candidate>
+    where
+        F: Fn(&Tool) -> Option<&Service>,
+    {
+        let mut found = false;
+        let mut v = Vec::new();
+        for tk in vt.map(|v| v.as_slice()).unwrap_or(&[]) {
+            let tool = &self.tools[tk.ix];
+            let status = if tool.path().is_none() {
+                CandidateStatus::NotFound
+            } else if !f(tool).map(|s| tool.supports(s)).unwrap_or(false) {
+                CandidateStatus::Unsupported
+            } else if found {
+                CandidateStatus::Available
+            } else {
+                found = true;
+                CandidateStatus::Selected
+            };
+            v.push(Candidate {
+                tool: tool.name().to_owned(),
+                priority: tk.priority,
+                status,
+            })
+        }
+        v
+    }
+
+    /// Describe the registered tools and the candidate tools for each format.  Note that this
+    /// runs each available tool to obtain its version.
+    pub fn info(&self) -> RegistryInfo {
+        let tools = self
+            .tools
+            .iter()
+            .map(|t| ToolInfo {
+                name: t.name().to_owned(),
+                path: t.path().map(|p| p.to_owned()),
+                version: t.version().cloned(),
+                compress: t
+                    .compress_services()
+                    .iter()
+                    .map(|s| s.compress_type())
+                    .collect(),
+                decompress: t
+                    .decompress_services()
+                    .iter()
+                    .map(|s| s.compress_type())
+                    .collect(),
+            })
+            .collect();
+        let formats = CompressType::formats()
+            .iter()
+            .map(|ct| FormatSelection {
+                compress_type: *ct,
+                compress: self.candidates(self.compress_tools.get(ct), |t| t.get_compress(*ct)),
+                decompress: self
+                    .candidates(self.decompress_tools.get(ct), |t| t.get_decompress(*ct)),
+            })
+            .collect();
+        RegistryInfo {
+            tools,
+            formats,
+            warnings: self.warnings.clone(),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Round trip tests of the tools in a [`ToolRegister`]
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+};
+
+use super::{Service, Tool, ToolRegister};
+use crate::compress_type::{get_ctype, CompressThreads, CompressType};
+
+/// Whether a service compresses or decompresses
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ServiceMode {
+    Compress,
+    Decompress,
+}
+
+impl fmt::Display for ServiceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compress => write!(f, "compress"),
+            Self::Decompress => write!(f, "decompress"),
+        }
+    }
+}
+
+/// Result of testing a single service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "PASS"),
+            Self::Failed(s) => write!(f, "FAIL ({})", s),
+            Self::Skipped(s) => write!(f, "SKIP ({})", s),
+        }
+    }
+}
+
+/// Test of a single service of a tool
+#[derive(Debug, Clone)]
+pub struct ServiceTest {
+    pub tool: String,
+    pub path: PathBuf,
+    pub mode: ServiceMode,
+    pub compress_type: CompressType,
+    pub outcome: TestOutcome,
+}
+
+impl fmt::Display for ServiceTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<10} {:<10} {:<10} {} [{}]",
+            self.tool,
+            self.mode.to_string(),
+            self.compress_type.to_string(),
+            self.outcome,
+            self.path.display()
+        )
+    }
+}
+
+/// Results from [`self_test`](crate::tools::self_test)
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    tests: Vec<ServiceTest>,
+}
+
+impl SelfTestReport {
+    pub fn tests(&self) -> &[ServiceTest] {
+        &self.tests
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ServiceTest> {
+        self.tests
+            .iter()
+            .filter(|t| matches!(t.outcome, TestOutcome::Failed(_)))
+    }
+
+    /// Returns true if no test failed
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for t in self.tests.iter() {
+            writeln!(f, "{}", t)?
+        }
+        let n_fail = self.failures().count();
+        write!(f, "{} services tested, {} failed", self.tests.len(), n_fail)
+    }
+}
+
+fn sample() -> Vec<u8> {
+    let mut v = Vec::new();
+    for i in 0..2000 {
+        writeln!(v, "compress_io self test line {}", i).unwrap();
+    }
+    v
+}
+
+// Run a service over input, returning the output
+fn run_service(tool: &Tool, service: &Service, input: &[u8]) -> io::Result<Vec<u8>> {
+    let path = tool.path().expect("Tool not available");
+    let threads = CompressThreads::Default;
+    let mut cmd = Command::new(path);
+    crate::hardened::apply(&mut cmd)?;
+    crate::hardened::set_envs(&mut cmd, service.envs(threads))?;
+    let mut child = cmd
+        .args(service.args(threads))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("pipe error");
+    let input = input.to_vec();
+    let wrt = thread::spawn(move || stdin.write_all(&input));
+    let mut output = Vec::new();
+    let rres = child
+        .stdout
+        .take()
+        .expect("pipe error")
+        .read_to_end(&mut output);
+    // If reading failed the tool may still be running, so stop it before waiting (which also
+    // lets the writer thread finish)
+    if rres.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait();
+    // An error writing is only of interest if the tool reported success
+    let wres = wrt.join().expect("writer thread panicked");
+    rres?;
+    let status = status?;
+    if !status.success() {
+        Err(io::Error::other(format!("exited with {}", status)))
+    } else {
+        wres.map(|_| output)
+    }
+}
+
+fn check_compress(tool: &Tool, service: &Service, input: &[u8]) -> (TestOutcome, Option<Vec<u8>>) {
+    let ct = service.compress_type();
+    match run_service(tool, service, input) {
+        Err(e) => (TestOutcome::Failed(e.to_string()), None),
+        Ok(v) if v.len() < 6 => (TestOutcome::Failed("output too short".to_string()), None),
+        Ok(v) => {
+            let found = get_ctype(&v);
+            // bgzip output is gzip compatible
+            if found == ct || (ct == CompressType::Gzip && found == CompressType::Bgzip) {
+                (TestOutcome::Passed, Some(v))
+            } else {
+                (
+                    TestOutcome::Failed(format!("output format detected as {}", found)),
+                    None,
+                )
+            }
+        }
+    }
+}
+
+fn check_decompress(tool: &Tool, service: &Service, input: &[u8], expected: &[u8]) -> TestOutcome {
+    match run_service(tool, service, input) {
+        Err(e) => TestOutcome::Failed(e.to_string()),
+        Ok(v) if v == expected => TestOutcome::Passed,
+        Ok(_) => TestOutcome::Failed("decompressed output does not match input".to_string()),
+    }
+}
+
+impl ToolRegister {
+    /// Test all services of available tools.  Compression services are tested by
+    /// compressing a sample and checking that the format of the output matches the
+    /// declared [`CompressType`]; decompression services are tested by decompressing
+    /// output generated by the compression services and comparing with the original sample.
+    pub fn self_test(&self) -> SelfTestReport {
+        let input = sample();
+        let mut tests = Vec::new();
+        let mut samples: HashMap<CompressType, Vec<u8>> = HashMap::new();
+
+        let mk_test = |tool: &Tool, s: &Service, mode, outcome| ServiceTest {
+            tool: tool.name().to_owned(),
+            path: tool.path().map(|p| p.to_owned()).unwrap_or_default(),
+            mode,
+            compress_type: s.compress_type(),
+            outcome,
+        };
+
+        let available = || self.tools().iter().filter(|t| t.path().is_some());
+        for tool in available() {
+            for s in tool.compress_services() {
+                let outcome = if tool.supports(s) {
+                    let (outcome, out) = check_compress(tool, s, &input);
+                    if let Some(v) = out {
+                        samples.entry(get_ctype(&v)).or_insert(v);
+                    }
+                    outcome
+                } else {
+                    TestOutcome::Skipped("requirements not met".to_string())
+                };
+                tests.push(mk_test(tool, s, ServiceMode::Compress, outcome))
+            }
+        }
+        for tool in available() {
+            for s in tool.decompress_services() {
+                let outcome = if !tool.supports(s) {
+                    TestOutcome::Skipped("requirements not met".to_string())
+                } else if let Some(v) = samples.get(&s.compress_type()) {
+                    check_decompress(tool, s, v, &input)
+                } else {
+                    TestOutcome::Skipped("no compressed sample available".to_string())
+                };
+                tests.push(mk_test(tool, s, ServiceMode::Decompress, outcome))
+            }
+        }
+        SelfTestReport { tests }
+    }
+}